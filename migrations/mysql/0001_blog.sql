@@ -1,7 +1,10 @@
 -- Jackson Coxson
--- List of tables used for jkcoxson.com
+-- Posts and how they're organized, as the site started out. Later columns are added
+-- by the migrations after this one, see src/db/mysql.rs
+-- The live database was made from this schema before there were migrations,
+-- IF NOT EXISTS lets it pick them up from here.
 
-CREATE TABLE posts (
+CREATE TABLE IF NOT EXISTS posts (
     slug VARCHAR(255) NOT NULL,
     post_name VARCHAR(255) NOT NULL,
     file_path VARCHAR(255) NOT NULL,
@@ -14,19 +17,19 @@ CREATE TABLE posts (
     PRIMARY KEY (slug)
 );
 
-CREATE TABLE categories (
+CREATE TABLE IF NOT EXISTS categories (
     id INT NOT NULL AUTO_INCREMENT,
     category_name VARCHAR(255) NOT NULL,
     PRIMARY KEY (id)
 );
 
-CREATE TABLE tags (
+CREATE TABLE IF NOT EXISTS tags (
     id INT NOT NULL AUTO_INCREMENT,
     tag_name VARCHAR(255) NOT NULL,
     PRIMARY KEY (id)
 );
 
-CREATE TABLE post_tags (
+CREATE TABLE IF NOT EXISTS post_tags (
     id INT NOT NULL AUTO_INCREMENT,
     slug VARCHAR(255) NOT NULL,
     tag_id INT NOT NULL,
@@ -0,0 +1,4 @@
+-- Jackson Coxson
+-- Who wrote a post, the default author when it's empty
+
+ALTER TABLE posts ADD COLUMN author VARCHAR(255) AFTER file_path;
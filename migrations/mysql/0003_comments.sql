@@ -0,0 +1,17 @@
+-- Jackson Coxson
+-- Reader comments, see src/blog/comments.rs
+
+CREATE TABLE comments (
+    id INT NOT NULL AUTO_INCREMENT,
+    slug VARCHAR(255) NOT NULL,
+    parent_id INT,
+    author_name VARCHAR(64) NOT NULL,
+    email VARCHAR(255),
+    body TEXT NOT NULL,
+    ip VARCHAR(45),
+    approved TINYINT NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    INDEX comments_post (slug, approved, created_at),
+    INDEX comments_queue (approved, created_at)
+);
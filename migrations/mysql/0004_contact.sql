@@ -0,0 +1,13 @@
+-- Jackson Coxson
+-- Messages sent from /contact, see src/contact/mod.rs
+
+CREATE TABLE contact_messages (
+    id INT NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    message TEXT NOT NULL,
+    ip VARCHAR(45),
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    INDEX contact_messages_created (created_at)
+);
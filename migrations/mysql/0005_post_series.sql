@@ -0,0 +1,7 @@
+-- Jackson Coxson
+-- Posts that belong together, see src/blog/series.rs
+
+ALTER TABLE posts
+    ADD COLUMN series VARCHAR(255),
+    ADD COLUMN series_part INT,
+    ADD INDEX posts_series (series);
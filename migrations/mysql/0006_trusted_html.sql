@@ -0,0 +1,4 @@
+-- Jackson Coxson
+-- Posts whose HTML isn't sanitized, see src/blog/render/sanitize.rs
+
+ALTER TABLE posts ADD COLUMN trusted_html TINYINT NOT NULL DEFAULT 0;
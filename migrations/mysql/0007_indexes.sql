@@ -0,0 +1,35 @@
+-- Jackson Coxson
+-- Indexes for the queries in src/db/mysql.rs
+
+ALTER TABLE posts
+    ADD INDEX posts_date_published (date_published),
+    ADD INDEX posts_live (published, date_published);
+
+-- Names could be used twice before they were unique,
+-- posts move to the lowest id with their name and the others are dropped
+UPDATE posts p
+    JOIN categories c ON c.id = p.category
+    JOIN (SELECT category_name, MIN(id) AS id FROM categories GROUP BY category_name) keep
+        ON keep.category_name = c.category_name
+    SET p.category = keep.id
+    WHERE p.category <> keep.id;
+DELETE c FROM categories c
+    JOIN categories keep ON keep.category_name = c.category_name AND keep.id < c.id;
+ALTER TABLE categories ADD UNIQUE INDEX categories_name (category_name);
+
+UPDATE post_tags pt
+    JOIN tags t ON t.id = pt.tag_id
+    JOIN (SELECT tag_name, MIN(id) AS id FROM tags GROUP BY tag_name) keep
+        ON keep.tag_name = t.tag_name
+    SET pt.tag_id = keep.id
+    WHERE pt.tag_id <> keep.id;
+DELETE t FROM tags t
+    JOIN tags keep ON keep.tag_name = t.tag_name AND keep.id < t.id;
+-- A post that had both of the merged tags would have it twice now
+DELETE pt FROM post_tags pt
+    JOIN post_tags keep ON keep.slug = pt.slug AND keep.tag_id = pt.tag_id AND keep.id < pt.id;
+ALTER TABLE tags ADD UNIQUE INDEX tags_name (tag_name);
+
+ALTER TABLE post_tags
+    ADD INDEX post_tags_pair (slug, tag_id),
+    ADD INDEX post_tags_tag (tag_id);
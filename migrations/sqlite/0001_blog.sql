@@ -0,0 +1,30 @@
+-- Jackson Coxson
+-- Posts and how they're organized, the same as migrations/mysql
+
+CREATE TABLE IF NOT EXISTS posts (
+    slug TEXT NOT NULL PRIMARY KEY,
+    post_name TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    sneak_peak TEXT,
+    image_path TEXT,
+    published INTEGER,
+    date_published TEXT NOT NULL,
+    date_updated TEXT,
+    category INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    category_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tag_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS post_tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    slug TEXT NOT NULL,
+    tag_id INTEGER NOT NULL
+);
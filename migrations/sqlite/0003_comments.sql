@@ -0,0 +1,16 @@
+-- Jackson Coxson
+-- Reader comments, see src/blog/comments.rs
+
+CREATE TABLE comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    slug TEXT NOT NULL,
+    parent_id INTEGER,
+    author_name TEXT NOT NULL,
+    email TEXT,
+    body TEXT NOT NULL,
+    ip TEXT,
+    approved INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX comments_post ON comments (slug, approved, created_at);
+CREATE INDEX comments_queue ON comments (approved, created_at);
@@ -0,0 +1,12 @@
+-- Jackson Coxson
+-- Messages sent from /contact, see src/contact/mod.rs
+
+CREATE TABLE contact_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    message TEXT NOT NULL,
+    ip TEXT,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX contact_messages_created ON contact_messages (created_at);
@@ -0,0 +1,6 @@
+-- Jackson Coxson
+-- Posts that belong together, see src/blog/series.rs
+
+ALTER TABLE posts ADD COLUMN series TEXT;
+ALTER TABLE posts ADD COLUMN series_part INTEGER;
+CREATE INDEX posts_series ON posts (series);
@@ -0,0 +1,28 @@
+-- Jackson Coxson
+-- Indexes for the queries in src/db/sqlite.rs
+
+CREATE INDEX posts_date_published ON posts (date_published);
+CREATE INDEX posts_live ON posts (published, date_published);
+
+-- Names could be used twice before they were unique,
+-- posts move to the lowest id with their name and the others are dropped
+UPDATE posts SET category = (
+    SELECT MIN(keep.id) FROM categories c
+    JOIN categories keep ON keep.category_name = c.category_name
+    WHERE c.id = posts.category
+) WHERE category IN (SELECT id FROM categories);
+DELETE FROM categories WHERE id NOT IN (SELECT MIN(id) FROM categories GROUP BY category_name);
+CREATE UNIQUE INDEX categories_name ON categories (category_name);
+
+UPDATE post_tags SET tag_id = (
+    SELECT MIN(keep.id) FROM tags t
+    JOIN tags keep ON keep.tag_name = t.tag_name
+    WHERE t.id = post_tags.tag_id
+) WHERE tag_id IN (SELECT id FROM tags);
+DELETE FROM tags WHERE id NOT IN (SELECT MIN(id) FROM tags GROUP BY tag_name);
+-- A post that had both of the merged tags would have it twice now
+DELETE FROM post_tags WHERE id NOT IN (SELECT MIN(id) FROM post_tags GROUP BY slug, tag_id);
+CREATE UNIQUE INDEX tags_name ON tags (tag_name);
+
+CREATE INDEX post_tags_pair ON post_tags (slug, tag_id);
+CREATE INDEX post_tags_tag ON post_tags (tag_id);
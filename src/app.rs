@@ -2,18 +2,24 @@ use crate::{
     blog,
     error_template::{AppError, ErrorTemplate},
     forge_component::ForgeComponent,
+    honeypot::Honeypot,
 };
 use chrono::Datelike;
 use leptos::prelude::*;
 use leptos_meta::*;
 use leptos_router::{
     components::{ParentRoute, Route, Router, Routes},
-    path,
+    path, SsrMode,
 };
 use rand::RngExt;
 pub fn shell(options: LeptosOptions) -> impl IntoView {
+    // Every script on the page needs this nonce to get past the CSP, see security.rs
+    #[cfg(feature = "ssr")]
+    crate::security::provide_page_policy();
+    let nonce = leptos::nonce::use_nonce().map(|n| n.to_string());
+
     view! {
-        <!DOCTYPE html> 
+        <!DOCTYPE html>
         <html lang="en">
             <head>
                 <meta charset="utf-8" />
@@ -21,7 +27,9 @@ pub fn shell(options: LeptosOptions) -> impl IntoView {
                     defer
                     src="https://analytics.jkcoxson.com/script.js"
                     data-website-id="2b9d06df-c74f-42a7-b6f8-76be381cb4d9"
+                    nonce=nonce.clone()
                 ></script>
+                <script id="main-js" src="/cdn/site/js/main.js" nonce=nonce></script>
                 <meta name="viewport" content="width=device-width, initial-scale=1" />
                 <AutoReload options=options.clone() />
                 <HydrationScripts options />
@@ -41,11 +49,6 @@ pub fn App() -> impl IntoView {
 
     view! {
         <Stylesheet id="leptos" href="/cdn/site/pkg/jkcoxson.css" />
-        <Script
-            id="highlighter-js"
-            src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/highlight.min.js"
-        />
-        <Script id="main-js" src="/cdn/site/js/main.js" />
 
         // <Eruda />
 
@@ -66,9 +69,27 @@ pub fn App() -> impl IntoView {
                         .into_view()
                 }>
                     <Route path=path!("") view=HomePage />
-                    <Route path=path!("/forge/*any") view=ForgeComponent />
-                    <Route path=path!("/blog") view=blog::browse::BrowseView />
-                    <Route path=path!("/blog/:id") view=blog::page::PageView />
+                    // Rendered fully before responding, so errors can set the status code
+                    <Route path=path!("/forge/*any") view=ForgeComponent ssr=SsrMode::Async />
+                    <Route path=path!("/blog") view=blog::browse::BrowseView ssr=SsrMode::Async />
+                    <Route
+                        path=path!("/blog/search")
+                        view=blog::search::SearchView
+                        ssr=SsrMode::Async
+                    />
+                    <Route
+                        path=path!("/blog/series/:name")
+                        view=blog::series::SeriesView
+                        ssr=SsrMode::Async
+                    />
+                    <Route
+                        path=path!("/blog/:id")
+                        view=blog::page::PageView
+                        ssr=SsrMode::PartiallyBlocked
+                    />
+                    <Route path=path!("/admin/blog") view=blog::admin::AdminBlogView />
+                    <Route path=path!("/admin/blog/new") view=blog::admin::PostEditorView />
+                    <Route path=path!("/admin/blog/edit/:id") view=blog::admin::PostEditorView />
                     <Route path=path!("/jitstreamer") view=crate::jitstreamer::Page />
                     <ParentRoute path=path!("/idevice-tools") view=crate::idevice_tools::Layout>
                         <Route path=path!("") view=crate::idevice_tools::ToolHome />
@@ -575,6 +596,8 @@ fn Project(title: String, description: String, image: String, link: String) -> i
 #[component]
 /// Contact me tile
 fn Contact() -> impl IntoView {
+    let send = ServerAction::<crate::contact::SendContactMessage>::new();
+    let email = LocalResource::new(crate::contact::get_contact_email);
     view! {
         <section class="relative m-6 py-16 md:m-44 lg:py-24">
             <div class="relative">
@@ -617,16 +640,48 @@ fn Contact() -> impl IntoView {
                                 </div>
                                 <div class="ml-4">
                                     <h6 class="text-lg font-semibold dark:text-white">Email</h6>
-                                    <p class="text-gray-600 dark:text-gray-200">Loading...</p>
+                                    <Suspense fallback=move || {
+                                        view! {
+                                            <p class="text-gray-600 dark:text-gray-200">
+                                                "Loading..."
+                                            </p>
+                                        }
+                                    }>
+                                        {move || {
+                                            email
+                                                .get()
+                                                .map(|email| match email {
+                                                    Ok(Some(email)) => {
+                                                        view! {
+                                                            <a
+                                                                class="text-gray-600 hover:underline dark:text-gray-200"
+                                                                href=format!("mailto:{email}")
+                                                            >
+                                                                {email}
+                                                            </a>
+                                                        }
+                                                            .into_any()
+                                                    }
+                                                    _ => {
+                                                        view! {
+                                                            <p class="text-gray-600 dark:text-gray-200">
+                                                                "Use the form"
+                                                            </p>
+                                                        }
+                                                            .into_any()
+                                                    }
+                                                })
+                                        }}
+                                    </Suspense>
                                 </div>
                             </div>
                         </div>
                     </div>
                     <div class="w-full md:w-1/2 lg:w-2/3">
                         <div>
-                            <form
-                                class="rounded-lg bg-white p-4 shadow-2xl dark:bg-stone-800"
-                                method="post"
+                            <ActionForm
+                                action=send
+                                attr:class="rounded-lg bg-white p-4 shadow-2xl dark:bg-stone-800"
                             >
                                 <div class="mb-4">
                                     <input
@@ -634,6 +689,8 @@ fn Contact() -> impl IntoView {
                                         type="text"
                                         id="name-1"
                                         name="name"
+                                        required
+                                        maxlength=crate::contact::MAX_NAME.to_string()
                                         placeholder="Name"
                                     />
                                 </div>
@@ -643,15 +700,20 @@ fn Contact() -> impl IntoView {
                                         type="email"
                                         id="email-1"
                                         name="email"
+                                        required
+                                        maxlength=crate::contact::MAX_EMAIL.to_string()
                                         placeholder="Email"
                                     />
                                 </div>
+                                <Honeypot />
                                 <div class="mb-4">
                                     <textarea
                                         class="form-input w-full rounded border p-3 dark:border-black dark:bg-stone-700"
                                         id="message-1"
                                         name="message"
                                         rows="6"
+                                        required
+                                        maxlength=crate::contact::MAX_MESSAGE.to_string()
                                         placeholder="Message"
                                     ></textarea>
                                 </div>
@@ -659,11 +721,35 @@ fn Contact() -> impl IntoView {
                                     <button
                                         class="w-full rounded bg-blue-500 py-3 text-white transition hover:bg-blue-600"
                                         type="submit"
+                                        disabled=move || send.pending().get()
                                     >
                                         Send
                                     </button>
                                 </div>
-                            </form>
+                                {move || match send.value().get() {
+                                    Some(Ok(())) => {
+                                        view! {
+                                            <p class="mt-4 text-green-600">
+                                                "Thanks! I'll get back to you soon."
+                                            </p>
+                                        }
+                                            .into_any()
+                                    }
+                                    Some(Err(AppError::InternalServerError)) => {
+                                        view! {
+                                            <p class="mt-4 text-red-600">
+                                                "Your message couldn't be sent, try again later."
+                                            </p>
+                                        }
+                                            .into_any()
+                                    }
+                                    Some(Err(e)) => {
+                                        view! { <p class="mt-4 text-red-600">{e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                    None => "".into_any(),
+                                }}
+                            </ActionForm>
                         </div>
                     </div>
                 </div>
@@ -710,10 +796,9 @@ fn BlogShowcase() -> impl IntoView {
                                         .into_any()
                                 }
                                 Err(e) => {
-                                    println!("Error fetching posts: {e:?}");
+                                    tracing::error!("Error fetching posts: {e:?}");
                                     let mut outside_errors = Errors::default();
-                                    outside_errors
-                                        .insert_with_default_key(AppError::InternalServerError);
+                                    outside_errors.insert_with_default_key(e);
                                     view! { <ErrorTemplate outside_errors /> }.into_any()
                                 }
                             }
@@ -852,10 +937,12 @@ pub fn Footer() -> impl IntoView {
 #[component]
 /// Mobile debug
 fn Eruda() -> impl IntoView {
+    #[cfg(debug_assertions)]
+    let nonce = leptos::nonce::use_nonce().map(|n| n.to_string());
     #[cfg(debug_assertions)]
     view! {
-        <Script id="eruda" src="https://cdn.jsdelivr.net/npm/eruda" />
-        <div inner_html="<script>eruda.init();</script>"></div>
+        <script id="eruda" src="https://cdn.jsdelivr.net/npm/eruda" nonce=nonce.clone()></script>
+        <script nonce=nonce>"eruda.init();"</script>
     }
 
     #[cfg(not(debug_assertions))]
@@ -0,0 +1,385 @@
+// Jackson Coxson
+//
+// Server functions behind /admin/blog.
+// Everything except logging in checks the session cookie first.
+// Posts made here are database rows with their markdown at forge/blog/<slug>/post.md,
+// so the forge watcher takes care of dropping stale renders.
+
+use leptos::prelude::*;
+use leptos::server_fn::codec::{MultipartData, MultipartFormData};
+
+use crate::blog::structures::{AdminPost, AdminPostSummary, Category, Tag};
+use crate::error_template::AppError;
+#[cfg(feature = "ssr")]
+use crate::{blog::admin::auth::require_admin, context::Context};
+
+/// Header images can be any of these. Not SVG, it can carry scripts and uploads
+/// are served from our own origin.
+#[cfg(feature = "ssr")]
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "avif"];
+
+#[server(AdminStatus, "/api", "getjson", "admin_status")]
+pub async fn admin_status() -> Result<bool, AppError> {
+    let state = expect_context::<Context>();
+    Ok(super::auth::is_admin(&state).await)
+}
+
+#[server(AdminLogin, "/api")]
+pub async fn admin_login(password: String) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    let Some(auth) = &state.admin else {
+        return Err(AppError::Forbidden);
+    };
+    if !auth.check_password(&password) {
+        // Not a real rate limit, but guessing gets a lot slower
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        return Err(AppError::BadRequest("Wrong password".to_string()));
+    }
+    super::auth::set_session_cookie(Some(auth.session()));
+    leptos_axum::redirect("/admin/blog");
+    Ok(())
+}
+
+#[server(AdminLogout, "/api")]
+pub async fn admin_logout() -> Result<(), AppError> {
+    super::auth::set_session_cookie(None);
+    leptos_axum::redirect("/admin/blog");
+    Ok(())
+}
+
+/// Every database post, drafts and scheduled ones included
+#[server(AdminPosts, "/api", "getjson", "admin_posts")]
+pub async fn admin_posts() -> Result<Vec<AdminPostSummary>, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    Ok(state
+        .db
+        .posts()
+        .await?
+        .into_iter()
+        .map(|p| AdminPostSummary {
+            slug: p.slug,
+            post_name: p.post_name,
+            published: p.published,
+            date_published: p.date_published,
+        })
+        .collect())
+}
+
+#[server(AdminPostForEdit, "/api", "getjson", "admin_post")]
+pub async fn admin_post(slug: String) -> Result<AdminPost, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    let Some(post) = state.db.post(&slug).await? else {
+        return Err(AppError::NotFound);
+    };
+    let markdown = match tokio::fs::read_to_string(&post.file_path).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::warn!("Unable to read {:?} for editing: {e:?}", post.file_path);
+            String::new()
+        }
+    };
+
+    Ok(AdminPost {
+        slug: post.slug,
+        post_name: post.post_name,
+        sneak_peak: post.sneak_peak,
+        image_path: post.image_path,
+        author: post.author,
+        published: post.published,
+        date_published: post.date_published,
+        category: post.category.map(|c| c.id),
+        tags: post.tags.into_iter().map(|t| t.id).collect(),
+        series: post.series.as_ref().map(|s| s.name.clone()),
+        series_part: post.series.and_then(|s| s.part),
+        trusted_html: post.trusted_html,
+        markdown,
+    })
+}
+
+/// Creates or updates a post and writes its markdown into the forge.
+/// Slugs can't change once a post exists, since links to it are already out there.
+/// New posts need a slug no source has and a folder that isn't in the forge yet.
+#[server(SavePost, "/api")]
+pub async fn save_post(post: AdminPost, is_new: bool) -> Result<String, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    if !valid_slug(&post.slug) {
+        return Err(AppError::BadRequest(
+            "Slugs can only have lowercase letters, numbers and dashes".to_string(),
+        ));
+    }
+    if post.post_name.trim().is_empty() {
+        return Err(AppError::BadRequest("Posts need a title".to_string()));
+    }
+
+    let file_path = if is_new {
+        // A file post's folder would be written over, and it would hide the new row anyway
+        if state.posts.post(&post.slug).await?.is_some() {
+            return Err(already_exists(&post.slug));
+        }
+        let dir = state.blog_dir.join(&post.slug);
+        tokio::fs::create_dir_all(&state.blog_dir).await?;
+        match tokio::fs::create_dir(&dir).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(already_exists(&post.slug))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        dir.join("post.md")
+    } else {
+        match state.db.post(&post.slug).await? {
+            Some(existing) => existing.file_path,
+            None => return Err(AppError::NotFound),
+        }
+    };
+
+    let post = AdminPost {
+        post_name: post.post_name.trim().to_string(),
+        author: non_empty(post.author),
+        sneak_peak: non_empty(post.sneak_peak),
+        image_path: non_empty(post.image_path),
+        series: non_empty(post.series),
+        ..post
+    };
+    let saved = match tokio::fs::write(&file_path, &post.markdown).await {
+        Ok(()) => state
+            .db
+            .save_post(&post, &file_path.to_string_lossy(), is_new)
+            .await
+            .map_err(AppError::from),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = saved {
+        // Don't leave a folder behind that would show up as a post on its own
+        if is_new {
+            if let Some(dir) = file_path.parent() {
+                if let Err(e) = tokio::fs::remove_dir_all(dir).await {
+                    tracing::warn!("Unable to clean up {dir:?} after a failed save: {e:?}");
+                }
+            }
+        }
+        return Err(e);
+    }
+
+    state.search_index.mark_dirty();
+    Ok(post.slug)
+}
+
+#[server(SetPublished, "/api")]
+pub async fn set_published(slug: String, published: bool) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    state.db.set_published(&slug, published).await?;
+    state.search_index.mark_dirty();
+    Ok(())
+}
+
+/// Renders the editor's markdown exactly like a published post would be,
+/// with a warning box listing any broken links or images
+#[server(RenderPreview, "/api")]
+pub async fn render_preview(
+    slug: String,
+    markdown: String,
+    trusted_html: bool,
+) -> Result<String, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    // Relative paths resolve against the folder the post is (or will be) saved in
+    let post_dir = match state.posts.post(&slug).await {
+        Ok(Some(post)) => post.file_path.parent().map(|p| p.to_path_buf()),
+        _ => None,
+    }
+    .unwrap_or_else(|| state.blog_dir.join(&slug));
+    let ext = crate::blog::render::Extensions {
+        forge_root: Some(&state.forge_dir),
+        post_dir: Some(&post_dir),
+        trusted_html,
+        ..Default::default()
+    };
+    match crate::blog::render::render_post(&markdown, &ext) {
+        Ok(r) if r.warnings.is_empty() => Ok(r.html),
+        Ok(r) => {
+            let warnings = r
+                .warnings
+                .iter()
+                .map(|w| format!("<li>{}</li>", crate::blog::render::escape(w)))
+                .collect::<String>();
+            Ok(format!(
+                "<div class=\"admonition admonition-warning\"><p class=\"admonition-title\">Warning</p><ul>{warnings}</ul></div>\n{}",
+                r.html
+            ))
+        }
+        Err(e) => Err(AppError::BadRequest(e)),
+    }
+}
+
+#[server(AdminCategories, "/api", "getjson", "admin_categories")]
+pub async fn admin_categories() -> Result<Vec<Category>, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    Ok(state.db.categories().await?)
+}
+
+#[server(CreateCategory, "/api")]
+pub async fn create_category(name: String) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Categories need a name".to_string()));
+    }
+    state.db.create_category(name).await?;
+    Ok(())
+}
+
+/// Posts in the category are left without one
+#[server(DeleteCategory, "/api")]
+pub async fn delete_category(id: i32) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    state.db.delete_category(id).await?;
+    Ok(())
+}
+
+#[server(AdminTags, "/api", "getjson", "admin_tags")]
+pub async fn admin_tags() -> Result<Vec<Tag>, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    Ok(state.db.tags().await?)
+}
+
+#[server(CreateTag, "/api")]
+pub async fn create_tag(name: String) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Tags need a name".to_string()));
+    }
+    state.db.create_tag(name).await?;
+    Ok(())
+}
+
+#[server(DeleteTag, "/api")]
+pub async fn delete_tag(id: i32) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    state.db.delete_tag(id).await?;
+    state.search_index.mark_dirty();
+    Ok(())
+}
+
+/// Takes a form with a `slug` field followed by an `image` file, saves the
+/// image next to the post in the forge and returns the URL it's served at
+#[server(input = MultipartFormData, prefix = "/api", endpoint = "upload_post_image")]
+pub async fn upload_post_image(data: MultipartData) -> Result<String, AppError> {
+    let state = expect_context::<Context>();
+    require_admin(&state).await?;
+    let Some(mut data) = data.into_inner() else {
+        return Err(AppError::BadRequest("Expected a form".to_string()));
+    };
+
+    let mut slug = None;
+    while let Ok(Some(field)) = data.next_field().await {
+        match field.name() {
+            Some("slug") => slug = field.text().await.ok(),
+            Some("image") => {
+                let Some(slug) = slug.take().filter(|s| valid_slug(s)) else {
+                    return Err(AppError::BadRequest(
+                        "Save the post before uploading images".to_string(),
+                    ));
+                };
+                let Some(name) = field.file_name().and_then(image_file_name) else {
+                    return Err(AppError::BadRequest(format!(
+                        "Images have to be one of {}",
+                        IMAGE_EXTENSIONS.join(", ")
+                    )));
+                };
+                let bytes = match field.bytes().await {
+                    Ok(b) => b,
+                    Err(e) => return Err(AppError::BadRequest(e.to_string())),
+                };
+
+                let dir = state.blog_dir.join(&slug);
+                tokio::fs::create_dir_all(&dir).await?;
+                tokio::fs::write(dir.join(&name), bytes).await?;
+                return Ok(format!("/cdn/blog/{slug}/{name}"));
+            }
+            _ => {}
+        }
+    }
+    Err(AppError::BadRequest("No image was sent".to_string()))
+}
+
+#[cfg(feature = "ssr")]
+fn already_exists(slug: &str) -> AppError {
+    AppError::BadRequest(format!("There's already a post at /blog/{slug}"))
+}
+
+#[cfg(feature = "ssr")]
+fn valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && !slug.starts_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Keeps uploads to plain names with an image extension, so they can't escape the post's folder
+#[cfg(feature = "ssr")]
+fn image_file_name(name: &str) -> Option<String> {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()?
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let (stem, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    if stem.is_empty() || stem.starts_with('.') || !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some(format!("{stem}.{extension}"))
+}
+
+/// Empty form fields are stored as NULL
+#[cfg(feature = "ssr")]
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_names() {
+        assert!(valid_slug("my-post-2"));
+        assert!(!valid_slug("../etc"));
+        assert!(!valid_slug("My Post"));
+        assert!(!valid_slug(""));
+
+        assert_eq!(image_file_name("Header.PNG").as_deref(), Some("Header.png"));
+        assert_eq!(
+            image_file_name("../../my photo.jpg").as_deref(),
+            Some("my-photo.jpg")
+        );
+        assert_eq!(
+            image_file_name("C:\\stuff\\a.webp").as_deref(),
+            Some("a.webp")
+        );
+        assert_eq!(image_file_name("script.html"), None);
+        assert_eq!(image_file_name("logo.svg"), None);
+        assert_eq!(image_file_name(".png"), None);
+        assert_eq!(image_file_name("noextension"), None);
+    }
+}
@@ -0,0 +1,127 @@
+// Jackson Coxson
+//
+// There's only one admin, so there are no accounts.
+// Logging in with ADMIN_PASSWORD gets you a signed session cookie, and
+// changing the password signs everyone out.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use leptos::prelude::*;
+
+use crate::{blog::preview::PreviewKey, context::Context, error_template::AppError};
+
+pub const COOKIE: &str = "admin_session";
+const SESSION_LIFETIME: chrono::Duration = chrono::Duration::days(7);
+/// What session tokens are signed over, there's nothing else to tell them apart by
+const SUBJECT: &str = "admin";
+
+#[derive(Clone)]
+pub struct AdminAuth {
+    key: PreviewKey,
+}
+
+impl AdminAuth {
+    pub fn new(password: &str) -> Self {
+        Self {
+            key: key_for(password),
+        }
+    }
+
+    /// None when ADMIN_PASSWORD isn't set, which turns the admin pages off
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("ADMIN_PASSWORD") {
+            Ok(p) if !p.is_empty() => Some(Self::new(&p)),
+            _ => None,
+        }
+    }
+
+    /// Compares signatures rather than the passwords so it takes the same time either way
+    pub fn check_password(&self, attempt: &str) -> bool {
+        let token = key_for(attempt).sign(SUBJECT, 0);
+        self.key.verify(SUBJECT, &token, 0)
+    }
+
+    pub fn session(&self) -> String {
+        self.key.sign_for(SUBJECT, SESSION_LIFETIME)
+    }
+
+    pub fn verify_session(&self, token: &str) -> bool {
+        self.key
+            .verify(SUBJECT, token, chrono::Utc::now().timestamp())
+    }
+}
+
+fn key_for(password: &str) -> PreviewKey {
+    PreviewKey::new(format!("admin-session:{password}"))
+}
+
+/// Errors unless the request carries a valid session cookie
+pub async fn require_admin(state: &Context) -> Result<(), AppError> {
+    if is_admin(state).await {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized)
+    }
+}
+
+pub async fn is_admin(state: &Context) -> bool {
+    let Some(auth) = &state.admin else {
+        return false;
+    };
+    let Ok(headers) = leptos_axum::extract::<HeaderMap>().await else {
+        return false;
+    };
+    cookie(&headers, COOKIE).is_some_and(|t| auth.verify_session(&t))
+}
+
+/// Sets the session cookie, or clears it when given None
+pub fn set_session_cookie(token: Option<String>) {
+    let Some(response) = use_context::<leptos_axum::ResponseOptions>() else {
+        return;
+    };
+    let cookie = match token {
+        Some(t) => format!(
+            "{COOKIE}={t}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict",
+            SESSION_LIFETIME.num_seconds()
+        ),
+        None => format!("{COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Strict"),
+    };
+    if let Ok(v) = HeaderValue::from_str(&cookie) {
+        response.append_header(header::SET_COOKIE, v);
+    }
+}
+
+pub(crate) fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions() {
+        let auth = AdminAuth::new("hunter2");
+        assert!(auth.check_password("hunter2"));
+        assert!(!auth.check_password("hunter3"));
+        assert!(!auth.check_password(""));
+
+        let session = auth.session();
+        assert!(auth.verify_session(&session));
+        assert!(!AdminAuth::new("changed").verify_session(&session));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {COOKIE}={session}")).unwrap(),
+        );
+        assert_eq!(cookie(&headers, COOKIE), Some(session));
+        assert_eq!(cookie(&headers, "missing"), None);
+    }
+}
@@ -0,0 +1,745 @@
+// Jackson Coxson
+//
+// The blog's admin area at /admin/blog.
+// Only database posts are managed here, file based posts are edited in the forge directly.
+// Comments are moderated from here too.
+
+use std::time::Duration;
+
+use crate::app::{Footer, NavBar};
+use crate::blog::structures::{AdminPost, AdminPostSummary};
+use crate::error_template::{AppError, ErrorTemplate};
+use leptos::prelude::*;
+use leptos_meta::Title;
+use leptos_router::hooks::{use_navigate, use_params_map};
+
+pub mod api;
+#[cfg(feature = "ssr")]
+pub mod auth;
+
+use api::*;
+
+/// What `<input type="datetime-local">` gives and takes
+const DATE_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+/// How long typing has to pause before the preview is rendered again
+const PREVIEW_DELAY: Duration = Duration::from_millis(400);
+
+const INPUT_CLASS: &str =
+    "form-input w-full rounded border p-2 dark:border-black dark:bg-stone-700";
+const BUTTON_CLASS: &str = "rounded bg-blue-500 px-4 py-2 text-white disabled:opacity-50";
+
+#[component]
+pub fn AdminBlogView() -> impl IntoView {
+    view! {
+        <AdminPage title="Blog Admin">
+            <Dashboard />
+        </AdminPage>
+    }
+}
+
+#[component]
+pub fn PostEditorView() -> impl IntoView {
+    view! {
+        <AdminPage title="Edit Post">
+            <PostEditor />
+        </AdminPage>
+    }
+}
+
+/// Shows the login form instead of the page until there's a session
+#[component]
+fn AdminPage(title: &'static str, children: ChildrenFn) -> impl IntoView {
+    let status = Resource::new(|| (), |_| async move { admin_status().await });
+    view! {
+        <NavBar />
+        <Title text=title />
+        <div class="flex justify-center">
+            <div class="m-6 flex w-11/12 flex-col">
+                <h1 class="m-6">{title}</h1>
+                <Suspense fallback=move || {
+                    view! { <h2>"Loading..."</h2> }
+                }>
+                    {move || match status.get() {
+                        Some(Ok(true)) => children().into_any(),
+                        Some(Ok(false)) => view! { <LoginForm /> }.into_any(),
+                        Some(Err(e)) => {
+                            tracing::error!("Unable to check the admin session: {e:?}");
+                            let mut outside_errors = Errors::default();
+                            outside_errors.insert_with_default_key(e);
+                            view! { <ErrorTemplate outside_errors /> }.into_any()
+                        }
+                        None => view! { <h2>"Loading..."</h2> }.into_any(),
+                    }}
+                </Suspense>
+            </div>
+        </div>
+        <br />
+        <Footer />
+    }
+}
+
+#[component]
+fn LoginForm() -> impl IntoView {
+    let login = ServerAction::<AdminLogin>::new();
+    view! {
+        <ActionForm action=login attr:class="m-6 flex gap-2">
+            <input
+                type="password"
+                name="password"
+                placeholder="Password"
+                autocomplete="current-password"
+                class=INPUT_CLASS
+            />
+            <button type="submit" class=BUTTON_CLASS disabled=move || login.pending().get()>
+                "Log in"
+            </button>
+        </ActionForm>
+        <ActionError value=login.value() />
+    }
+}
+
+#[component]
+fn Dashboard() -> impl IntoView {
+    let toggle = ServerAction::<SetPublished>::new();
+    let logout = ServerAction::<AdminLogout>::new();
+    let posts = Resource::new(
+        move || toggle.version().get(),
+        |_| async move { admin_posts().await },
+    );
+
+    view! {
+        <div class="m-6 flex gap-4">
+            <a href="/admin/blog/new" class=BUTTON_CLASS>
+                "New post"
+            </a>
+            <ActionForm action=logout>
+                <button type="submit" class="rounded border px-4 py-2">
+                    "Log out"
+                </button>
+            </ActionForm>
+        </div>
+        <h2 class="m-6">"Posts"</h2>
+        <ActionError value=toggle.value() />
+        <Transition fallback=move || {
+            view! { <p>"Loading..."</p> }
+        }>
+            {move || match posts.get() {
+                Some(Ok(posts)) => {
+                    view! {
+                        <table class="m-6 text-left">
+                            <tbody>
+                                {posts
+                                    .into_iter()
+                                    .map(|p| view! { <PostRow post=p toggle /> })
+                                    .collect::<Vec<_>>()}
+                            </tbody>
+                        </table>
+                    }
+                        .into_any()
+                }
+                Some(Err(e)) => view! { <p class="m-6 text-red-600">{e.to_string()}</p> }.into_any(),
+                None => view! { <p>"Loading..."</p> }.into_any(),
+            }}
+        </Transition>
+        <ModerationQueue />
+        <Taxonomy />
+    }
+}
+
+/// Comments waiting to be approved, shown as the raw markdown they were sent as
+#[component]
+fn ModerationQueue() -> impl IntoView {
+    use crate::blog::comments::{pending_comments, ApproveComment, DeleteComment};
+
+    let approve = ServerAction::<ApproveComment>::new();
+    let delete = ServerAction::<DeleteComment>::new();
+    let pending = Resource::new(
+        move || (approve.version().get(), delete.version().get()),
+        |_| async move { pending_comments().await },
+    );
+
+    view! {
+        <h2 class="m-6">"Comments awaiting approval"</h2>
+        <ActionError value=approve.value() />
+        <ActionError value=delete.value() />
+        <Transition fallback=move || {
+            view! { <p>"Loading..."</p> }
+        }>
+            {move || match pending.get() {
+                Some(Ok(comments)) if comments.is_empty() => {
+                    view! { <p class="m-6 text-gray-500">"Nothing to moderate."</p> }.into_any()
+                }
+                Some(Ok(comments)) => {
+                    comments
+                        .into_iter()
+                        .map(|c| {
+                            let id = c.id;
+                            view! {
+                                <div class="m-6 border-b pb-4 dark:border-gray-700">
+                                    <div class="text-sm text-gray-500">
+                                        <span class="font-semibold">{c.author_name}</span>
+                                        {c.email.map(|e| format!(" <{e}>"))}
+                                        " on "
+                                        <a href=format!("/blog/{}", c.slug)>{c.slug.clone()}</a>
+                                        {c.parent_id.map(|p| format!(" (reply to #{p})"))}
+                                        " · "
+                                        {c.created_at.format("%Y-%m-%d %H:%M").to_string()}
+                                    </div>
+                                    <pre class="whitespace-pre-wrap">{c.body}</pre>
+                                    <div class="flex gap-2">
+                                        <button
+                                            class="rounded border px-2 py-1 text-sm"
+                                            on:click=move |_| {
+                                                approve.dispatch(ApproveComment { id });
+                                            }
+                                        >
+                                            "Approve"
+                                        </button>
+                                        <button
+                                            class="rounded border px-2 py-1 text-sm text-red-600"
+                                            on:click=move |_| {
+                                                delete.dispatch(DeleteComment { id });
+                                            }
+                                        >
+                                            "Delete"
+                                        </button>
+                                    </div>
+                                </div>
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .into_any()
+                }
+                Some(Err(e)) => view! { <p class="m-6 text-red-600">{e.to_string()}</p> }.into_any(),
+                None => view! { <p>"Loading..."</p> }.into_any(),
+            }}
+        </Transition>
+    }
+}
+
+#[component]
+fn PostRow(post: AdminPostSummary, toggle: ServerAction<SetPublished>) -> impl IntoView {
+    let now = chrono::Utc::now().naive_utc();
+    let status = match (post.published, post.date_published <= now) {
+        (false, _) => "Draft",
+        (true, false) => "Scheduled",
+        (true, true) => "Published",
+    };
+    let slug = post.slug.clone();
+    view! {
+        <tr class="border-b dark:border-gray-700">
+            <td class="p-2">
+                <a href=format!("/admin/blog/edit/{}", post.slug)>{post.post_name}</a>
+            </td>
+            <td class="p-2 text-sm text-gray-500">
+                {post.date_published.format("%Y-%m-%d %H:%M").to_string()}
+            </td>
+            <td class="p-2">{status}</td>
+            <td class="p-2">
+                <button
+                    class="rounded border px-2 py-1 text-sm"
+                    on:click=move |_| {
+                        toggle
+                            .dispatch(SetPublished {
+                                slug: slug.clone(),
+                                published: !post.published,
+                            });
+                    }
+                >
+                    {if post.published { "Unpublish" } else { "Publish" }}
+                </button>
+            </td>
+        </tr>
+    }
+}
+
+/// Lists the categories and tags with forms to add and remove them
+#[component]
+fn Taxonomy() -> impl IntoView {
+    let create_category = ServerAction::<CreateCategory>::new();
+    let delete_category = ServerAction::<DeleteCategory>::new();
+    let create_tag = ServerAction::<CreateTag>::new();
+    let delete_tag = ServerAction::<DeleteTag>::new();
+    let categories = Resource::new(
+        move || {
+            (
+                create_category.version().get(),
+                delete_category.version().get(),
+            )
+        },
+        |_| async move { admin_categories().await },
+    );
+    let tags = Resource::new(
+        move || (create_tag.version().get(), delete_tag.version().get()),
+        |_| async move { admin_tags().await },
+    );
+
+    view! {
+        <div class="m-6 grid gap-8 md:grid-cols-2">
+            <div>
+                <h2>"Categories"</h2>
+                <ActionForm action=create_category attr:class="my-2 flex gap-2">
+                    <input type="text" name="name" placeholder="New category" class=INPUT_CLASS />
+                    <button type="submit" class=BUTTON_CLASS>
+                        "Add"
+                    </button>
+                </ActionForm>
+                <ActionError value=create_category.value() />
+                <Transition>
+                    <ul>
+                        {move || {
+                            categories
+                                .get()
+                                .and_then(Result::ok)
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|c| {
+                                    let id = c.id;
+                                    view! {
+                                        <li class="flex justify-between border-b p-1 dark:border-gray-700">
+                                            {c.category_name}
+                                            <button
+                                                class="text-sm text-red-600"
+                                                on:click=move |_| {
+                                                    delete_category.dispatch(DeleteCategory { id });
+                                                }
+                                            >
+                                                "Delete"
+                                            </button>
+                                        </li>
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        }}
+                    </ul>
+                </Transition>
+            </div>
+            <div>
+                <h2>"Tags"</h2>
+                <ActionForm action=create_tag attr:class="my-2 flex gap-2">
+                    <input type="text" name="name" placeholder="New tag" class=INPUT_CLASS />
+                    <button type="submit" class=BUTTON_CLASS>
+                        "Add"
+                    </button>
+                </ActionForm>
+                <ActionError value=create_tag.value() />
+                <Transition>
+                    <ul>
+                        {move || {
+                            tags
+                                .get()
+                                .and_then(Result::ok)
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|t| {
+                                    let id = t.id;
+                                    view! {
+                                        <li class="flex justify-between border-b p-1 dark:border-gray-700">
+                                            {t.tage_name}
+                                            <button
+                                                class="text-sm text-red-600"
+                                                on:click=move |_| {
+                                                    delete_tag.dispatch(DeleteTag { id });
+                                                }
+                                            >
+                                                "Delete"
+                                            </button>
+                                        </li>
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        }}
+                    </ul>
+                </Transition>
+            </div>
+        </div>
+    }
+}
+
+/// Loads the post being edited, or a blank one at /admin/blog/new
+#[component]
+fn PostEditor() -> impl IntoView {
+    let params = use_params_map();
+    let post = Resource::new(
+        move || params.get().get("id"),
+        |slug| async move {
+            match slug {
+                Some(slug) => admin_post(slug).await.map(|p| (p, false)),
+                None => Ok((
+                    AdminPost {
+                        date_published: chrono::Utc::now().naive_utc(),
+                        ..Default::default()
+                    },
+                    true,
+                )),
+            }
+        },
+    );
+
+    view! {
+        <Suspense fallback=move || {
+            view! { <h2>"Loading..."</h2> }
+        }>
+            {move || match post.get() {
+                Some(Ok((post, is_new))) => view! { <Editor post is_new /> }.into_any(),
+                Some(Err(AppError::NotFound)) => {
+                    let mut outside_errors = Errors::default();
+                    outside_errors.insert_with_default_key(AppError::NotFound);
+                    view! { <ErrorTemplate outside_errors /> }.into_any()
+                }
+                Some(Err(e)) => view! { <p class="m-6 text-red-600">{e.to_string()}</p> }.into_any(),
+                None => view! { <h2>"Loading..."</h2> }.into_any(),
+            }}
+        </Suspense>
+    }
+}
+
+#[component]
+fn Editor(post: AdminPost, is_new: bool) -> impl IntoView {
+    let slug = RwSignal::new(post.slug);
+    let title = RwSignal::new(post.post_name);
+    let sneak_peak = RwSignal::new(post.sneak_peak.unwrap_or_default());
+    let image_path = RwSignal::new(post.image_path.unwrap_or_default());
+    let author = RwSignal::new(post.author.unwrap_or_default());
+    let published = RwSignal::new(post.published);
+    let date = RwSignal::new(post.date_published.format(DATE_INPUT_FORMAT).to_string());
+    let category = RwSignal::new(post.category);
+    let tags = RwSignal::new(post.tags);
+    let series = RwSignal::new(post.series.unwrap_or_default());
+    let series_part = RwSignal::new(post.series_part.map(|p| p.to_string()).unwrap_or_default());
+    let trusted_html = RwSignal::new(post.trusted_html);
+    let markdown = RwSignal::new(post.markdown);
+
+    // Rendered on the server so it goes through the exact same pipeline as the real post
+    let preview_source = RwSignal::new(markdown.get_untracked());
+    let preview = Resource::new(
+        move || (preview_source.get(), trusted_html.get()),
+        move |(md, trusted)| async move { render_preview(slug.get_untracked(), md, trusted).await },
+    );
+    let pending_preview = StoredValue::new(None::<TimeoutHandle>);
+    let on_markdown = move |ev| {
+        markdown.set(event_target_value(&ev));
+        if let Some(handle) = pending_preview.get_value() {
+            handle.clear();
+        }
+        pending_preview.set_value(
+            set_timeout_with_handle(
+                move || preview_source.set(markdown.get_untracked()),
+                PREVIEW_DELAY,
+            )
+            .ok(),
+        );
+    };
+
+    let categories = Resource::new(|| (), |_| async move { admin_categories().await });
+    let all_tags = Resource::new(|| (), |_| async move { admin_tags().await });
+
+    let save = Action::new(move |post: &AdminPost| {
+        let post = post.clone();
+        async move { save_post(post, is_new).await }
+    });
+    let navigate = use_navigate();
+    Effect::new(move || {
+        if let Some(Ok(slug)) = save.value().get() {
+            if is_new {
+                navigate(&format!("/admin/blog/edit/{slug}"), Default::default());
+            }
+        }
+    });
+    let on_save = move |_| {
+        let Some(date_published) = parse_date_input(&date.get_untracked()) else {
+            save.value().set(Some(Err(AppError::BadRequest(
+                "The publish date isn't valid".to_string(),
+            ))));
+            return;
+        };
+        save.dispatch(AdminPost {
+            slug: slug.get_untracked(),
+            post_name: title.get_untracked(),
+            sneak_peak: Some(sneak_peak.get_untracked()),
+            image_path: Some(image_path.get_untracked()),
+            author: Some(author.get_untracked()),
+            published: published.get_untracked(),
+            date_published,
+            category: category.get_untracked(),
+            tags: tags.get_untracked(),
+            series: Some(series.get_untracked()),
+            series_part: series_part.get_untracked().trim().parse().ok(),
+            trusted_html: trusted_html.get_untracked(),
+            markdown: markdown.get_untracked(),
+        });
+    };
+
+    view! {
+        <div class="m-6 flex flex-col gap-3">
+            <label>
+                "Slug"
+                <input
+                    type="text"
+                    class=INPUT_CLASS
+                    prop:value=slug
+                    disabled=!is_new
+                    on:input=move |ev| slug.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                "Title"
+                <input
+                    type="text"
+                    class=INPUT_CLASS
+                    prop:value=title
+                    on:input=move |ev| title.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                "Sneak peak"
+                <input
+                    type="text"
+                    class=INPUT_CLASS
+                    prop:value=sneak_peak
+                    on:input=move |ev| sneak_peak.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                "Author (blank for the default)"
+                <input
+                    type="text"
+                    class=INPUT_CLASS
+                    prop:value=author
+                    on:input=move |ev| author.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                "Header image"
+                <input
+                    type="text"
+                    class=INPUT_CLASS
+                    prop:value=image_path
+                    on:input=move |ev| image_path.set(event_target_value(&ev))
+                />
+            </label>
+            <ImageUpload slug is_new image_path />
+            <div class="flex flex-wrap items-end gap-6">
+                <label>
+                    "Series (blank for none)"
+                    <input
+                        type="text"
+                        class=INPUT_CLASS
+                        prop:value=series
+                        on:input=move |ev| series.set(event_target_value(&ev))
+                    />
+                </label>
+                <label>
+                    "Part"
+                    <input
+                        type="number"
+                        min="1"
+                        class=INPUT_CLASS
+                        prop:value=series_part
+                        on:input=move |ev| series_part.set(event_target_value(&ev))
+                    />
+                </label>
+            </div>
+            <div class="flex flex-wrap items-end gap-6">
+                <label>
+                    "Publish date (UTC)"
+                    <input
+                        type="datetime-local"
+                        class=INPUT_CLASS
+                        prop:value=date
+                        on:input=move |ev| date.set(event_target_value(&ev))
+                    />
+                </label>
+                <label class="flex items-center gap-2">
+                    <input
+                        type="checkbox"
+                        prop:checked=published
+                        on:change=move |ev| published.set(event_target_checked(&ev))
+                    />
+                    "Published"
+                </label>
+                <label class="flex items-center gap-2" title="Keep raw HTML like <script> as written">
+                    <input
+                        type="checkbox"
+                        prop:checked=trusted_html
+                        on:change=move |ev| trusted_html.set(event_target_checked(&ev))
+                    />
+                    "Trusted HTML"
+                </label>
+                <label>
+                    "Category"
+                    <select
+                            class=INPUT_CLASS
+                            on:change=move |ev| category.set(event_target_value(&ev).parse().ok())
+                        >
+                            <option value="" prop:selected=move || category.get().is_none()>
+                                "None"
+                            </option>
+                            {move || {
+                                categories
+                                    .get()
+                                    .and_then(Result::ok)
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .map(|c| {
+                                        let id = c.id;
+                                        view! {
+                                            <option
+                                                value=id.to_string()
+                                                prop:selected=move || category.get() == Some(id)
+                                            >
+                                                {c.category_name}
+                                            </option>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()
+                            }}
+                    </select>
+                </label>
+            </div>
+            <div class="flex flex-wrap gap-4">
+                {move || {
+                    all_tags
+                            .get()
+                            .and_then(Result::ok)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|t| {
+                                let id = t.id;
+                                view! {
+                                    <label class="flex items-center gap-1">
+                                        <input
+                                            type="checkbox"
+                                            prop:checked=move || tags.with(|s| s.contains(&id))
+                                            on:change=move |ev| {
+                                                let checked = event_target_checked(&ev);
+                                                tags.update(|s| {
+                                                    s.retain(|t| *t != id);
+                                                    if checked {
+                                                        s.push(id);
+                                                    }
+                                                });
+                                            }
+                                        />
+                                        {t.tage_name}
+                                    </label>
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                }}
+            </div>
+            <div class="grid gap-4 lg:grid-cols-2">
+                <textarea
+                    class=format!("{INPUT_CLASS} min-h-[60vh] font-mono text-sm")
+                    prop:value=markdown
+                    on:input=on_markdown
+                ></textarea>
+                <div class="post min-h-[60vh] overflow-y-auto rounded border p-4 dark:border-gray-700">
+                    <Transition fallback=move || {
+                        view! { <p>"Rendering..."</p> }
+                    }>
+                        {move || match preview.get() {
+                            Some(Ok(html)) => view! { <div inner_html=html></div> }.into_any(),
+                            Some(Err(e)) => {
+                                view! { <p class="text-red-600">{e.to_string()}</p> }.into_any()
+                            }
+                            None => view! { <p>"Rendering..."</p> }.into_any(),
+                        }}
+                    </Transition>
+                </div>
+            </div>
+            <div class="flex items-center gap-4">
+                <button class=BUTTON_CLASS on:click=on_save disabled=move || save.pending().get()>
+                    {move || if save.pending().get() { "Saving..." } else { "Save" }}
+                </button>
+                {move || match save.value().get() {
+                    Some(Ok(_)) => view! { <span class="text-green-600">"Saved"</span> }.into_any(),
+                    Some(Err(e)) => view! { <span class="text-red-600">{e.to_string()}</span> }.into_any(),
+                    None => "".into_any(),
+                }}
+            </div>
+        </div>
+    }
+}
+
+/// Uploads a header image into the post's forge folder and uses it
+#[component]
+fn ImageUpload(
+    slug: RwSignal<String>,
+    is_new: bool,
+    image_path: RwSignal<String>,
+) -> impl IntoView {
+    let status = RwSignal::<Option<Result<String, String>>>::new(None);
+    let busy = RwSignal::new(false);
+
+    let on_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        #[cfg(target_arch = "wasm32")]
+        {
+            use wasm_bindgen::JsCast;
+            let Some(form) = ev
+                .target()
+                .and_then(|t| t.dyn_into::<web_sys::HtmlFormElement>().ok())
+            else {
+                return;
+            };
+            let Ok(data) = web_sys::FormData::new_with_form(&form) else {
+                return;
+            };
+            busy.set(true);
+            wasm_bindgen_futures::spawn_local(async move {
+                match upload_post_image(data.into()).await {
+                    Ok(url) => {
+                        image_path.set(url.clone());
+                        status.set(Some(Ok(url)));
+                    }
+                    Err(e) => status.set(Some(Err(e.to_string()))),
+                }
+                busy.set(false);
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = (image_path, busy);
+    };
+
+    if is_new {
+        return view! { <p class="text-sm text-gray-500">"Save the post to upload images"</p> }
+            .into_any();
+    }
+    view! {
+        <form on:submit=on_submit class="flex items-center gap-2" enctype="multipart/form-data">
+            // The slug has to come before the file, the server reads them in order
+            <input type="hidden" name="slug" prop:value=slug />
+            <input type="file" name="image" accept=".png,.jpg,.jpeg,.gif,.webp,.avif" class="text-sm" />
+            <button type="submit" class="rounded border px-2 py-1 text-sm" disabled=busy>
+                "Upload"
+            </button>
+            {move || match status.get() {
+                Some(Ok(url)) => {
+                    view! { <span class="text-sm text-green-600">{format!("Uploaded to {url}")}</span> }
+                        .into_any()
+                }
+                Some(Err(e)) => view! { <span class="text-sm text-red-600">{e}</span> }.into_any(),
+                None => "".into_any(),
+            }}
+        </form>
+    }
+        .into_any()
+}
+
+#[component]
+fn ActionError(value: RwSignal<Option<Result<(), AppError>>>) -> impl IntoView {
+    move || {
+        value
+            .get()
+            .and_then(Result::err)
+            .map(|e| view! { <p class="m-6 text-red-600">{e.to_string()}</p> })
+    }
+}
+
+/// Browsers leave the seconds off unless one was set
+fn parse_date_input(s: &str) -> Option<chrono::NaiveDateTime> {
+    chrono::NaiveDateTime::parse_from_str(s, DATE_INPUT_FORMAT)
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+}
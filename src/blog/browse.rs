@@ -17,6 +17,7 @@ pub fn BrowseView() -> impl IntoView {
         <div class="flex justify-center">
             <div class="m-6 flex w-5/6 flex-col md:w-3/4">
                 <h1 class="m-6">"Blog Posts"</h1>
+                <SearchBox query=String::new() />
                 <hr />
                 <Suspense fallback=move || {
                     view! { <h2>"Loading..."</h2> }
@@ -38,10 +39,9 @@ pub fn BrowseView() -> impl IntoView {
                                         .into_any()
                                 }
                                 Err(e) => {
-                                    println!("Error fetching posts: {e:?}");
+                                    tracing::error!("Error fetching posts: {e:?}");
                                     let mut outside_errors = Errors::default();
-                                    outside_errors
-                                        .insert_with_default_key(AppError::InternalServerError);
+                                    outside_errors.insert_with_default_key(e);
                                     view! { <ErrorTemplate outside_errors /> }.into_any()
                                 }
                             }
@@ -57,8 +57,32 @@ pub fn BrowseView() -> impl IntoView {
     }
 }
 
+/// Plain GET form so searching works before hydration
 #[component]
-fn PostPreviewComponent(preview: crate::blog::structures::PostPreview) -> impl IntoView {
+pub fn SearchBox(query: String) -> impl IntoView {
+    view! {
+        <form action="/blog/search" method="get" class="m-6 flex gap-2">
+            <input
+                type="search"
+                name="q"
+                value=query
+                placeholder="Search posts"
+                class="form-input w-full rounded border p-2 dark:border-black dark:bg-stone-700"
+            />
+            <button type="submit" class="rounded bg-blue-500 px-4 py-2 text-white">
+                "Search"
+            </button>
+        </form>
+    }
+}
+
+#[component]
+pub fn PostPreviewComponent(
+    preview: crate::blog::structures::PostPreview,
+    /// Highlighted HTML from the search index
+    #[prop(optional)]
+    snippet: Option<String>,
+) -> impl IntoView {
     view! {
         <a
             href=format!("/blog/{}", preview.slug)
@@ -72,7 +96,35 @@ fn PostPreviewComponent(preview: crate::blog::structures::PostPreview) -> impl I
                     "".into_any()
                 }} <div class="flex-grow">
                     <h3 class="mb-1 text-lg font-semibold">{preview.post_name}</h3>
-                    <p class="mb-1 text-gray-600 dark:text-gray-200">{preview.sneak_peak}</p>
+                    {preview
+                        .series
+                        .map(|s| {
+                            let part = s.part.map(|p| format!("Part {p} of ")).unwrap_or_default();
+                            view! {
+                                <p class="mb-1 text-sm text-gray-500">
+                                    {format!("{part}{}", s.name)}
+                                </p>
+                            }
+                        })}
+                    {match snippet {
+                        Some(snippet) => {
+                            view! {
+                                <p
+                                    class="search-snippet mb-1 text-gray-600 dark:text-gray-200"
+                                    inner_html=snippet
+                                ></p>
+                            }
+                                .into_any()
+                        }
+                        None => {
+                            view! {
+                                <p class="mb-1 text-gray-600 dark:text-gray-200">
+                                    {preview.sneak_peak}
+                                </p>
+                            }
+                                .into_any()
+                        }
+                    }}
                 </div> <div class="text-sm text-gray-500">
                     <small>{preview.relative_date}</small>
                 </div>
@@ -85,102 +137,46 @@ fn PostPreviewComponent(preview: crate::blog::structures::PostPreview) -> impl I
 pub async fn get_posts(
     page: Option<u16>,
     limit: Option<u16>,
-) -> Result<Vec<super::structures::PostPreview>, ServerFnError> {
+) -> Result<Vec<super::structures::PostPreview>, AppError> {
     let state = expect_context::<Context>();
+    query_previews(&state, page, limit).await
+}
 
-    let posts = match sqlx::query_as::<_, crate::blog::structures::raw::RawPostPreview>(
-        r#"
-SELECT
-    posts.post_name,
-    posts.slug,
-    posts.sneak_peak,
-    posts.image_path,
-    posts.published,
-    posts.date_published,
-    posts.date_updated,
-    posts.category,
-    categories.category_name
-FROM posts
-LEFT JOIN categories ON posts.category = categories.id
-ORDER BY posts.date_published DESC
-LIMIT ?,?;
-"#,
-    )
-    .bind(page.unwrap_or(0))
-    .bind(match limit {
-        Some(l) => l + 1,
-        None => u16::MAX,
-    })
-    .fetch_all(&state.sql_pool)
-    .await
-    {
-        Ok(p) => p,
-        Err(e) => {
-            println!("Error fetching post previews from the database: {:?}", e);
-            return Err(ServerFnError::ServerError(e.to_string()));
-        }
-    };
+/// Loads the previews of posts that are published and past their publish date, newest first
+#[cfg(feature = "ssr")]
+pub async fn query_previews(
+    state: &Context,
+    page: Option<u16>,
+    limit: Option<u16>,
+) -> Result<Vec<super::structures::PostPreview>, AppError> {
+    let posts = state.posts.live_posts().await?;
 
-    let mut set = tokio::task::JoinSet::new();
-    posts.into_iter().for_each(|p| {
-        let pool = state.sql_pool.clone();
-        set.spawn(async move {
-            let tags = match sqlx::query_as::<_, super::structures::Tag>(
-                r#"
-SELECT 
-    tags.id, 
-    tags.tag_name 
-FROM post_tags 
-LEFT JOIN tags ON post_tags.tag_id = tags.id 
-WHERE post_tags.slug = ?;"#,
-            )
-            .bind(&p.slug)
-            .fetch_all(&pool)
-            .await
-            {
-                Ok(t) => t,
-                Err(e) => {
-                    println!("Unable to fetch the tags for post {}: {e:?}", &p.slug);
-                    Vec::new()
-                }
-            };
-            super::structures::PostPreview {
-                post_name: p.post_name,
-                slug: p.slug,
-                sneak_peak: p.sneak_peak,
-                image_path: p.image_path,
-                published: p.published.unwrap_or(false),
-                date_published: p.date_published,
-                relative_date: format_relative_time(p.date_published),
-                date_updated: p.date_updated.map(format_relative_time),
-                category: match p.category {
-                    Some(c) => Some(super::structures::Category {
-                        id: c,
-                        category_name: p.category_name.unwrap(),
-                    }),
-                    None => None,
-                },
-                tags,
-            }
-        });
-    });
+    Ok(posts
+        .into_iter()
+        .skip(page.unwrap_or(0) as usize)
+        .take(match limit {
+            Some(l) => l as usize + 1,
+            None => usize::MAX,
+        })
+        .map(preview)
+        .collect())
+}
 
-    let mut previews = Vec::with_capacity(set.len());
-    while let Some(preview) = set.join_next().await {
-        match preview {
-            Ok(p) => {
-                if p.published {
-                    previews.push(p)
-                }
-                continue;
-            }
-            Err(e) => {
-                println!("Unable to join preview future! {e:?}");
-            }
-        }
+#[cfg(feature = "ssr")]
+pub fn preview(p: super::source::PostRecord) -> super::structures::PostPreview {
+    super::structures::PostPreview {
+        post_name: p.post_name,
+        slug: p.slug,
+        sneak_peak: p.sneak_peak,
+        image_path: p.image_path,
+        published: p.published,
+        date_published: p.date_published,
+        relative_date: format_relative_time(p.date_published),
+        date_updated: p.date_updated.map(format_relative_time),
+        category: p.category,
+        tags: p.tags,
+        series: p.series,
     }
-    previews.sort_by(|a, b| b.date_published.cmp(&a.date_published));
-    Ok(previews)
 }
 
 #[cfg(feature = "ssr")]
@@ -0,0 +1,117 @@
+// Jackson Coxson
+//
+// Rendered posts are kept around until the file they came from changes.
+// An entry is only used if the file's mtime and the render options match
+// what it was built with, and the forge watcher drops entries as soon as
+// their file is touched.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use hashlink::LinkedHashMap;
+
+use super::{
+    absolute,
+    render::{RenderedPost, OPTIONS_HASH},
+};
+
+struct CachedPost {
+    path: PathBuf,
+    modified: SystemTime,
+    options: u64,
+    post: RenderedPost,
+}
+
+#[derive(Clone)]
+pub struct PostCache {
+    inner: Arc<Mutex<LinkedHashMap<String, CachedPost>>>,
+    limit: usize,
+}
+
+impl PostCache {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(LinkedHashMap::with_capacity(limit))),
+            limit,
+        }
+    }
+
+    /// Returns the cached render if it was made from the same version of the file
+    pub fn get(&self, slug: &str, modified: SystemTime) -> Option<RenderedPost> {
+        let mut cache = self.inner.lock().unwrap();
+        let entry = cache.to_back(slug)?;
+        if entry.modified == modified && entry.options == *OPTIONS_HASH {
+            return Some(entry.post.clone());
+        }
+        cache.remove(slug);
+        None
+    }
+
+    pub fn insert(&self, slug: String, path: &Path, modified: SystemTime, post: RenderedPost) {
+        if self.limit == 0 {
+            return;
+        }
+        let mut cache = self.inner.lock().unwrap();
+        cache.remove(&slug);
+        if cache.len() >= self.limit {
+            cache.pop_front();
+        }
+        cache.insert(
+            slug,
+            CachedPost {
+                path: absolute(path),
+                modified,
+                options: *OPTIONS_HASH,
+                post,
+            },
+        );
+    }
+
+    /// Drops every post whose file is at or under one of the changed paths
+    pub fn invalidate(&self, paths: &[PathBuf]) {
+        let paths: Vec<PathBuf> = paths.iter().map(|p| absolute(p)).collect();
+        self.inner
+            .lock()
+            .unwrap()
+            .retain(|_, entry| !paths.iter().any(|p| entry.path.starts_with(p)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> RenderedPost {
+        RenderedPost {
+            html: String::new(),
+            toc: Vec::new(),
+            word_count: 0,
+            reading_time: 1,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn bounded_and_invalidated() {
+        let cache = PostCache::new(2);
+        let t = SystemTime::UNIX_EPOCH;
+        cache.insert("a".into(), Path::new("forge/blog/a/post.md"), t, post());
+        cache.insert("b".into(), Path::new("forge/blog/b/post.md"), t, post());
+        assert!(cache.get("a", t).is_some());
+        cache.insert("c".into(), Path::new("forge/blog/c/post.md"), t, post());
+        assert!(
+            cache.get("b", t).is_none(),
+            "least recently used is evicted"
+        );
+        assert!(cache.get("a", t).is_some());
+        assert!(cache
+            .get("a", t + std::time::Duration::from_secs(1))
+            .is_none());
+
+        cache.invalidate(&[PathBuf::from("forge/blog/c")]);
+        assert!(cache.get("c", t).is_none());
+    }
+}
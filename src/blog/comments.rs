@@ -0,0 +1,347 @@
+// Jackson Coxson
+//
+// Comments under blog posts.
+// New comments go into a moderation queue and only show up once approved from /admin/blog.
+// The bodies are markdown, rendered without raw HTML every time they're shown.
+
+use crate::blog::structures::{Comment, PendingComment};
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+use crate::honeypot::Honeypot;
+use leptos::prelude::*;
+
+const MAX_NAME: usize = 64;
+const MAX_EMAIL: usize = 255;
+const MAX_BODY: usize = 5_000;
+/// Replies deeper than this are drawn at the same indent
+const MAX_INDENT: usize = 4;
+
+#[component]
+pub fn CommentsSection(slug: String) -> impl IntoView {
+    let submit = ServerAction::<SubmitComment>::new();
+    let reply_to = RwSignal::<Option<(i32, String)>>::new(None);
+    let fetch_slug = slug.clone();
+    let comments = Resource::new(
+        || (),
+        move |_| {
+            let slug = fetch_slug.clone();
+            async move { get_comments(slug).await }
+        },
+    );
+
+    view! {
+        <section class="mt-12 border-t pt-6 dark:border-gray-700">
+            <h2>"Comments"</h2>
+            <Transition fallback=move || {
+                view! { <p>"Loading comments..."</p> }
+            }>
+                {move || match comments.get() {
+                    Some(Ok(comments)) if comments.is_empty() => {
+                        view! { <p class="text-gray-500">"No comments yet."</p> }.into_any()
+                    }
+                    Some(Ok(comments)) => {
+                        comments
+                            .into_iter()
+                            .map(|c| comment_view(c, 0, reply_to))
+                            .collect::<Vec<_>>()
+                            .into_any()
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("Unable to load comments: {e:?}");
+                        view! { <p class="text-gray-500">"Comments couldn't be loaded."</p> }
+                            .into_any()
+                    }
+                    None => view! { <p>"Loading comments..."</p> }.into_any(),
+                }}
+            </Transition>
+
+            <h3 class="mt-6">
+                {move || match reply_to.get() {
+                    Some((_, name)) => format!("Reply to {name}"),
+                    None => "Leave a comment".to_string(),
+                }}
+            </h3>
+            <Show when=move || reply_to.with(|r| r.is_some())>
+                <button class="text-sm text-blue-500" on:click=move |_| reply_to.set(None)>
+                    "Cancel reply"
+                </button>
+            </Show>
+            <ActionForm action=submit attr:class="flex flex-col gap-2">
+                <input type="hidden" name="slug" value=slug />
+                {move || {
+                    reply_to
+                        .get()
+                        .map(|(id, _)| {
+                            view! { <input type="hidden" name="parent_id" value=id.to_string() /> }
+                        })
+                }}
+                <input
+                    type="text"
+                    name="name"
+                    required
+                    maxlength=MAX_NAME.to_string()
+                    placeholder="Name"
+                    class="form-input rounded border p-2 dark:border-black dark:bg-stone-700"
+                />
+                <input
+                    type="email"
+                    name="email"
+                    maxlength=MAX_EMAIL.to_string()
+                    placeholder="Email (optional, never shown)"
+                    class="form-input rounded border p-2 dark:border-black dark:bg-stone-700"
+                />
+                <Honeypot />
+                <textarea
+                    name="body"
+                    required
+                    rows="5"
+                    maxlength=MAX_BODY.to_string()
+                    placeholder="Markdown is supported"
+                    class="form-textarea rounded border p-2 dark:border-black dark:bg-stone-700"
+                ></textarea>
+                <button
+                    type="submit"
+                    class="self-start rounded bg-blue-500 px-4 py-2 text-white"
+                    disabled=move || submit.pending().get()
+                >
+                    "Post comment"
+                </button>
+            </ActionForm>
+            {move || match submit.value().get() {
+                Some(Ok(())) => {
+                    view! {
+                        <p class="text-green-600">
+                            "Thanks! Your comment will show up once it's been approved."
+                        </p>
+                    }
+                        .into_any()
+                }
+                Some(Err(AppError::InternalServerError)) => {
+                    view! { <p class="text-red-600">"Your comment couldn't be sent."</p> }.into_any()
+                }
+                Some(Err(e)) => view! { <p class="text-red-600">{e.to_string()}</p> }.into_any(),
+                None => "".into_any(),
+            }}
+        </section>
+    }
+}
+
+/// Recursive, so it's a plain function returning `AnyView`
+fn comment_view(
+    comment: Comment,
+    depth: usize,
+    reply_to: RwSignal<Option<(i32, String)>>,
+) -> AnyView {
+    let id = comment.id;
+    let name = comment.author_name.clone();
+    let datetime = comment.created_at.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let date = comment.created_at.format("%B %-d, %Y").to_string();
+    view! {
+        <div
+            class="mt-4 border-l-2 pl-4 dark:border-gray-700"
+            class:ml-6=depth > 0 && depth <= MAX_INDENT
+        >
+            <div class="text-sm text-gray-500">
+                <span class="font-semibold">{comment.author_name}</span>
+                " · "
+                <time datetime=datetime>{date}</time>
+            </div>
+            <div class="comment" inner_html=comment.html></div>
+            <button
+                class="text-sm text-blue-500"
+                on:click=move |_| reply_to.set(Some((id, name.clone())))
+            >
+                "Reply"
+            </button>
+            {comment
+                .replies
+                .into_iter()
+                .map(|r| comment_view(r, depth + 1, reply_to))
+                .collect::<Vec<_>>()}
+        </div>
+    }
+    .into_any()
+}
+
+#[server(GetComments, "/api", "getjson", "get_comments")]
+pub async fn get_comments(slug: String) -> Result<Vec<Comment>, AppError> {
+    let state = expect_context::<Context>();
+    let rows = state.db.approved_comments(&slug).await?;
+
+    let comments = rows
+        .into_iter()
+        .map(|row| Comment {
+            id: row.id,
+            parent_id: row.parent_id,
+            author_name: row.author_name,
+            html: crate::blog::render::render_comment(&row.body).unwrap_or_default(),
+            created_at: row.created_at,
+            replies: Vec::new(),
+        })
+        .collect();
+    Ok(thread(comments))
+}
+
+#[server(SubmitComment, "/api")]
+pub async fn submit_comment(
+    slug: String,
+    parent_id: Option<i32>,
+    name: String,
+    email: Option<String>,
+    body: String,
+    website: Option<String>,
+) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+
+    if crate::honeypot::is_bot(&website) {
+        return Ok(());
+    }
+
+    let name = name.trim();
+    let body = body.trim();
+    let email = email
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty());
+    if name.is_empty() || name.chars().count() > MAX_NAME {
+        return Err(AppError::BadRequest(format!(
+            "Names have to be between 1 and {MAX_NAME} characters"
+        )));
+    }
+    if body.is_empty() || body.chars().count() > MAX_BODY {
+        return Err(AppError::BadRequest(format!(
+            "Comments have to be between 1 and {MAX_BODY} characters"
+        )));
+    }
+    if email
+        .as_ref()
+        .is_some_and(|e| e.len() > MAX_EMAIL || !e.contains('@'))
+    {
+        return Err(AppError::BadRequest(
+            "That email doesn't look right".to_string(),
+        ));
+    }
+
+    // Only live posts can be commented on
+    super::page::visible_post(&state, &slug, None).await?;
+    if let Some(parent_id) = parent_id {
+        if !state.db.is_approved_comment(parent_id, &slug).await? {
+            return Err(AppError::BadRequest(
+                "That comment can't be replied to".to_string(),
+            ));
+        }
+    }
+
+    let ip = crate::rate_limit::client_ip().await;
+    if let Err(wait) = state.comment_limiter.check(ip) {
+        return Err(AppError::rate_limited(wait));
+    }
+
+    let comment = crate::db::NewComment {
+        slug: &slug,
+        parent_id,
+        author_name: name,
+        email: email.as_deref(),
+        body,
+        ip: ip.map(|i| i.to_string()),
+    };
+    state.db.add_comment(comment).await?;
+    Ok(())
+}
+
+/// The moderation queue, oldest first
+#[server(PendingComments, "/api", "getjson", "pending_comments")]
+pub async fn pending_comments() -> Result<Vec<PendingComment>, AppError> {
+    let state = expect_context::<Context>();
+    super::admin::auth::require_admin(&state).await?;
+    Ok(state.db.pending_comments().await?)
+}
+
+#[server(ApproveComment, "/api")]
+pub async fn approve_comment(id: i32) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    super::admin::auth::require_admin(&state).await?;
+    state.db.approve_comment(id).await?;
+    Ok(())
+}
+
+/// Deletes a comment along with every reply under it
+#[server(DeleteComment, "/api")]
+pub async fn delete_comment(id: i32) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+    super::admin::auth::require_admin(&state).await?;
+    let mut ids = vec![id];
+    let mut frontier = vec![id];
+    while let Some(parent) = frontier.pop() {
+        let children = state.db.comment_replies(parent).await?;
+        ids.extend(children.iter().copied());
+        frontier.extend(children);
+    }
+    for id in ids {
+        state.db.delete_comment(id).await?;
+    }
+    Ok(())
+}
+
+/// Nests a flat, oldest first list of comments under their parents.
+/// Replies to comments that aren't in the list are dropped.
+#[cfg(feature = "ssr")]
+fn thread(comments: Vec<Comment>) -> Vec<Comment> {
+    use std::collections::HashMap;
+
+    fn attach(comment: &mut Comment, children: &mut HashMap<i32, Vec<Comment>>) {
+        if let Some(mut replies) = children.remove(&comment.id) {
+            for reply in replies.iter_mut() {
+                attach(reply, children);
+            }
+            comment.replies = replies;
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut children: HashMap<i32, Vec<Comment>> = HashMap::new();
+    for comment in comments {
+        match comment.parent_id {
+            Some(parent) => children.entry(parent).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+    for root in roots.iter_mut() {
+        attach(root, &mut children);
+    }
+    roots
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    fn comment(id: i32, parent_id: Option<i32>) -> Comment {
+        Comment {
+            id,
+            parent_id,
+            author_name: String::new(),
+            html: String::new(),
+            created_at: chrono::NaiveDateTime::default(),
+            replies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn threading() {
+        let threaded = thread(vec![
+            comment(1, None),
+            comment(2, Some(1)),
+            comment(3, None),
+            comment(4, Some(2)),
+            comment(5, Some(1)),
+            comment(6, Some(99)),
+        ]);
+        assert_eq!(threaded.len(), 2);
+        assert_eq!(threaded[0].id, 1);
+        let replies: Vec<_> = threaded[0].replies.iter().map(|c| c.id).collect();
+        assert_eq!(replies, [2, 5]);
+        assert_eq!(threaded[0].replies[0].replies[0].id, 4);
+        assert!(threaded[1].replies.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+// Jackson Coxson
+//
+// Post folders live in the forge, which serves everything in it at /cdn and lists it at /forge.
+// A post's markdown and metadata are never served from there, they're only shown rendered,
+// and the rest of a draft's (or scheduled post's) folder needs the post's preview token.
+// Opening a preview leaves the token in a cookie scoped to the folder, so its images load.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use leptos::prelude::*;
+
+use super::{
+    preview::PreviewKey,
+    source::{PostRecord, PostSources},
+};
+
+/// forge/blog, as the first part of a forge path
+const BLOG_FOLDER: &str = "blog";
+/// The files a post folder is read from, see source/files.rs
+const SOURCE_FILES: [&str; 2] = ["post.md", "post.toml"];
+const COOKIE: &str = "preview";
+
+/// Whether anyone can see the post right now, or `token` is its preview token
+pub fn visible(post: &PostRecord, key: Option<&PreviewKey>, token: Option<&str>) -> bool {
+    let now = chrono::Utc::now();
+    if post.is_live(now.naive_utc()) {
+        return true;
+    }
+    match (key, token) {
+        (Some(key), Some(token)) => key.verify(&post.slug, token, now.timestamp()),
+        _ => false,
+    }
+}
+
+/// Whether a forge path, without the /cdn or /forge in front, can be served
+pub async fn can_serve(
+    posts: &PostSources,
+    key: Option<&PreviewKey>,
+    path: &[&str],
+    token: Option<&str>,
+) -> bool {
+    let path = parts(path);
+    let [BLOG_FOLDER, slug, rest @ ..] = path.as_slice() else {
+        return true;
+    };
+    if let [name] = rest {
+        if SOURCE_FILES.contains(name) {
+            return false;
+        }
+    }
+    match posts.post(slug).await {
+        Ok(Some(post)) => visible(&post, key, token),
+        // Not a post, like a folder of images shared between posts
+        Ok(None) => true,
+        Err(e) => {
+            tracing::warn!("Not serving blog/{slug}, unable to tell if it's live: {e}");
+            false
+        }
+    }
+}
+
+/// Takes what can't be served out of a forge listing,
+/// the drafts in forge/blog and the source files in a post's folder
+pub async fn hide_entries(
+    posts: &PostSources,
+    key: Option<&PreviewKey>,
+    path: &[&str],
+    dirs: &mut Vec<String>,
+    files: &mut Vec<String>,
+) {
+    let path = parts(path);
+    match path.as_slice() {
+        [BLOG_FOLDER] => match posts.posts().await {
+            Ok(posts) => dirs.retain(|d| {
+                posts
+                    .iter()
+                    .find(|p| &p.slug == d)
+                    .is_none_or(|p| visible(p, key, None))
+            }),
+            Err(e) => {
+                tracing::warn!("Not listing blog/, unable to tell which posts are live: {e}");
+                dirs.clear();
+            }
+        },
+        [BLOG_FOLDER, _] => files.retain(|f| !SOURCE_FILES.contains(&f.as_str())),
+        _ => {}
+    }
+}
+
+/// Paths can have empty parts from doubled or trailing slashes
+fn parts<'a>(path: &[&'a str]) -> Vec<&'a str> {
+    path.iter().copied().filter(|p| !p.is_empty()).collect()
+}
+
+/// The preview token from `?preview=`, or the cookie left by opening the preview
+pub fn token(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == "preview")
+        .map(|(_, v)| v.to_string())
+        .or_else(|| super::admin::auth::cookie(headers, COOKIE))
+}
+
+/// Sends the token along with the preview page, for the requests to the post's folder
+pub fn remember_token(slug: &str, token: &str) {
+    let Some(response) = use_context::<leptos_axum::ResponseOptions>() else {
+        return;
+    };
+    let Some(expires) = super::preview::expires(token) else {
+        return;
+    };
+    let max_age = expires - chrono::Utc::now().timestamp();
+    let cookie = format!(
+        "{COOKIE}={token}; Path=/cdn/{BLOG_FOLDER}/{slug}/; Max-Age={max_age}; HttpOnly; Secure; SameSite=Lax"
+    );
+    if let Ok(v) = HeaderValue::from_str(&cookie) {
+        response.append_header(header::SET_COOKIE, v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::blog::source::FixedPosts;
+
+    fn path(p: &str) -> Vec<&str> {
+        p.split('/').collect()
+    }
+
+    #[tokio::test]
+    async fn drafts_stay_hidden() {
+        let key = Some(PreviewKey::new("secret"));
+        let key = key.as_ref();
+        let draft = PostRecord {
+            published: false,
+            ..PostRecord::fixture("draft", 2)
+        };
+        let posts = PostSources::new(vec![Arc::new(FixedPosts(vec![
+            PostRecord::fixture("live", 1),
+            draft,
+        ]))]);
+        let token = key.unwrap().sign_for("draft", chrono::Duration::hours(1));
+        let token = Some(token.as_str());
+
+        assert!(!can_serve(&posts, key, &path("blog/draft/post.md"), None).await);
+        assert!(!can_serve(&posts, key, &path("blog/draft/post.toml"), None).await);
+        assert!(!can_serve(&posts, key, &path("blog/draft/post.md"), token).await);
+        assert!(!can_serve(&posts, key, &path("blog/live/post.md"), None).await);
+        assert!(!can_serve(&posts, key, &path("blog/draft/header.png"), None).await);
+        assert!(!can_serve(&posts, key, &path("blog/draft/"), None).await);
+        assert!(!can_serve(&posts, key, &path("blog/draft/a.png"), Some("1.00")).await);
+        assert!(can_serve(&posts, key, &path("blog/draft/header.png"), token).await);
+        assert!(can_serve(&posts, key, &path("blog/live/header.png"), None).await);
+        assert!(can_serve(&posts, key, &path("blog/shared/logo.png"), None).await);
+        assert!(can_serve(&posts, key, &path("site/pkg/app.css"), None).await);
+
+        let mut dirs = vec!["draft".to_string(), "live".to_string()];
+        let mut files = vec!["post.md".to_string(), "header.png".to_string()];
+        hide_entries(&posts, key, &path("blog/"), &mut dirs, &mut files).await;
+        assert_eq!(dirs, ["live"]);
+        hide_entries(&posts, key, &path("blog/live"), &mut dirs, &mut files).await;
+        assert_eq!(files, ["header.png"]);
+    }
+
+    #[test]
+    fn tokens() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("preview=1.ab"));
+        assert_eq!(
+            token(&headers, Some("v=2&preview=9.cd")).as_deref(),
+            Some("9.cd")
+        );
+        assert_eq!(token(&headers, Some("v=2")).as_deref(), Some("1.ab"));
+        assert_eq!(token(&HeaderMap::new(), None), None);
+    }
+}
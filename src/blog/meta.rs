@@ -0,0 +1,163 @@
+// Jackson Coxson
+//
+// Link previews and structured data for blog posts.
+// Everything here comes from one blocking server function so the tags
+// are in the initial SSR response, where crawlers will actually see them.
+
+use leptos::prelude::*;
+use leptos_meta::{Link, Meta, Script};
+
+use super::structures::PostMetadata;
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+
+#[component]
+pub fn PostMetaTags(meta: PostMetadata) -> impl IntoView {
+    let published = meta.date_published.and_utc().to_rfc3339();
+    let updated = meta.date_updated.map(|d| d.and_utc().to_rfc3339());
+    let description = meta.description.clone().unwrap_or_default();
+    let json_ld = json_ld(&meta);
+    let card = if meta.image.is_some() {
+        "summary_large_image"
+    } else {
+        "summary"
+    };
+
+    view! {
+        <Link rel="canonical" href=meta.canonical_url.clone() />
+        <Meta name="description" content=description.clone() />
+        <Meta name="author" content=meta.author.clone() />
+
+        <Meta property="og:type" content="article" />
+        <Meta property="og:site_name" content="Jackson Coxson" />
+        <Meta property="og:title" content=meta.title.clone() />
+        <Meta property="og:description" content=description.clone() />
+        <Meta property="og:url" content=meta.canonical_url.clone() />
+        {meta.image.clone().map(|i| view! { <Meta property="og:image" content=i /> })}
+        <Meta property="article:published_time" content=published />
+        {updated.map(|u| view! { <Meta property="article:modified_time" content=u /> })}
+        <Meta property="article:author" content=meta.author.clone() />
+        {meta
+            .tags
+            .iter()
+            .map(|t| view! { <Meta property="article:tag" content=t.clone() /> })
+            .collect::<Vec<_>>()}
+
+        <Meta name="twitter:card" content=card />
+        <Meta name="twitter:title" content=meta.title.clone() />
+        <Meta name="twitter:description" content=description />
+        {meta.image.map(|i| view! { <Meta name="twitter:image" content=i /> })}
+
+        <Script type_="application/ld+json">{json_ld}</Script>
+    }
+}
+
+/// Builds the schema.org BlogPosting for the post.
+/// Script contents aren't escaped, so anything that could close the tag is
+/// written as a unicode escape instead.
+fn json_ld(meta: &PostMetadata) -> String {
+    let mut posting = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "BlogPosting",
+        "headline": meta.title,
+        "url": meta.canonical_url,
+        "mainEntityOfPage": meta.canonical_url,
+        "datePublished": meta.date_published.and_utc().to_rfc3339(),
+        "author": {
+            "@type": "Person",
+            "name": meta.author,
+        },
+        "keywords": meta.tags,
+    });
+    if let Some(d) = &meta.description {
+        posting["description"] = d.as_str().into();
+    }
+    if let Some(i) = &meta.image {
+        posting["image"] = i.as_str().into();
+    }
+    if let Some(u) = meta.date_updated {
+        posting["dateModified"] = u.and_utc().to_rfc3339().into();
+    }
+    posting
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+#[server(GetPostMetadata, "/api", "getjson", "get_post_metadata")]
+pub async fn get_post_metadata(
+    slug: String,
+    preview: Option<String>,
+) -> Result<PostMetadata, AppError> {
+    let state = expect_context::<Context>();
+    let post = super::page::visible_post(&state, &slug, preview.as_deref()).await?;
+    let live = post.is_live(chrono::Utc::now().naive_utc());
+
+    let meta = PostMetadata {
+        title: post.post_name,
+        description: post.sneak_peak,
+        image: post.image_path.map(|i| absolute_url(&i)),
+        canonical_url: format!("{}/blog/{slug}", super::SITE_URL),
+        author: post
+            .author
+            .unwrap_or_else(|| super::DEFAULT_AUTHOR.to_string()),
+        date_published: post.date_published,
+        date_updated: post.date_updated,
+        tags: post.tags.into_iter().map(|t| t.tage_name).collect(),
+    };
+
+    if !live {
+        hide_preview().await;
+        if let Some(token) = &preview {
+            super::drafts::remember_token(&slug, token);
+        }
+    } else {
+        revalidate();
+    }
+
+    Ok(meta)
+}
+
+/// Post pages can be cached, but have to be fetched again every time.
+/// There's no ETag to answer with a 304: the page's scripts carry the nonce of the response
+/// they came in, and a 304 would pair the cached page with a new CSP that blocks them.
+#[cfg(feature = "ssr")]
+fn revalidate() {
+    use axum::http::{header, HeaderValue};
+    use leptos_axum::ResponseOptions;
+
+    if let Some(response) = use_context::<ResponseOptions>() {
+        response.insert_header(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+}
+
+/// Preview pages shouldn't be cached or indexed, even if the link gets shared
+#[cfg(feature = "ssr")]
+async fn hide_preview() {
+    use axum::http::{header, HeaderName, HeaderValue};
+    use leptos_axum::ResponseOptions;
+
+    let Some(response) = use_context::<ResponseOptions>() else {
+        return;
+    };
+    response.insert_header(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-store"),
+    );
+    response.insert_header(
+        HeaderName::from_static("x-robots-tag"),
+        HeaderValue::from_static("noindex"),
+    );
+}
+
+/// Crawlers need absolute URLs for images
+#[cfg(feature = "ssr")]
+fn absolute_url(path: &str) -> String {
+    if path.starts_with("http://") || path.starts_with("https://") {
+        path.to_string()
+    } else {
+        format!("{}/{}", super::SITE_URL, path.trim_start_matches('/'))
+    }
+}
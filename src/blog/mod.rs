@@ -6,12 +6,50 @@
 // Each folder inside forge/blog is the slug that it should be served
 // so that we don't have to hold state in between requests
 // Each folder contains the post.md with the post contents, as well
-// as a post.toml with information about
+// as a post.toml (or front matter in post.md) with information about it.
+// Posts can also be listed in the database, see source/ for how the two are merged.
+// Database posts are written from /admin/blog, see admin/
 
+pub mod admin;
 pub mod browse;
+#[cfg(feature = "ssr")]
+pub mod cache;
+pub mod comments;
+#[cfg(feature = "ssr")]
+pub mod drafts;
+pub mod meta;
 pub mod page;
+#[cfg(feature = "ssr")]
+pub mod preview;
+pub mod related;
+#[cfg(feature = "ssr")]
+pub mod render;
+pub mod search;
+#[cfg(feature = "ssr")]
+pub mod search_index;
+pub mod series;
+#[cfg(feature = "ssr")]
+pub mod source;
 pub mod structures;
 
+/// Where the site is publicly served, used for canonical and preview links
+pub const SITE_URL: &str = "https://jkcoxson.com";
+/// Credited on posts that don't name an author
+pub const DEFAULT_AUTHOR: &str = "Jackson Coxson";
+
+/// Notify reports paths relative to how a folder was watched, and database rows can
+/// hold relative paths too, so paths are made absolute before being compared
+#[cfg(feature = "ssr")]
+pub fn absolute(path: &std::path::Path) -> std::path::PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|d| d.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
 #[cfg(feature = "ssr")]
 mod tests {
     #[allow(unused_imports)]
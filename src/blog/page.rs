@@ -1,22 +1,36 @@
 // Jackson Coxson
 
 use crate::app::{Footer, NavBar};
+use crate::blog::comments::CommentsSection;
+use crate::blog::meta::{get_post_metadata, PostMetaTags};
+use crate::blog::related::PostNavigationView;
+use crate::blog::series::SeriesNavigator;
+use crate::blog::structures::{PostContent, TocEntry};
 #[cfg(feature = "ssr")]
 use crate::context::Context;
 use crate::error_template::AppError;
 use crate::error_template::ErrorTemplate;
-use crate::highlightAll;
 use leptos::prelude::*;
 use leptos_meta::Title;
-use leptos_router::hooks::use_params_map;
+use leptos_router::hooks::{use_params_map, use_query_map};
 
 #[component]
 pub fn PageView() -> impl IntoView {
     let params = use_params_map();
-    let once = Resource::new(
-        move || params.get(),
-        |d| async move { get_post_content(d.get("id").clone().unwrap_or_default()).await },
-    );
+    let query = use_query_map();
+    // Drafts and scheduled posts are only shown with a signed ?preview= token
+    let post = move || {
+        (
+            params.get().get("id").unwrap_or_default(),
+            query.get().get("preview"),
+        )
+    };
+    let once = Resource::new(post, |(slug, preview)| async move {
+        get_post_content(slug, preview).await
+    });
+    let meta = Resource::new_blocking(post, |(slug, preview)| async move {
+        get_post_metadata(slug, preview).await
+    });
 
     view! {
         <NavBar />
@@ -24,46 +38,55 @@ pub fn PageView() -> impl IntoView {
             <Suspense fallback=move || {
                 view! { <h2>"Loading..."</h2> }
             }>
-
-                {
-                    Effect::new(move |_| {
-                        let _ = once.get();
-                        set_timeout(
-                            || {
-                                highlightAll();
-                            },
-                            std::time::Duration::from_millis(100),
-                        )
-                    });
-                }
-                <div class="flex w-5/6 md:w-3/4">
+                {move || {
+                    meta.get()
+                        .and_then(Result::ok)
+                        .map(|meta| view! { <PostMetaTags meta /> })
+                }}
+                <div class="flex w-5/6 lg:w-11/12 xl:w-3/4">
                     {move || match once.get() {
                         Some(data) => {
                             match data {
                                 Ok(data) => {
                                     view! {
-                                        <div class="post">
-                                            <Title text=data.1 />
-                                            <div inner_html=data.0></div>
+                                        <div class="flex w-full gap-8">
+                                            <div class="post min-w-0 flex-grow">
+                                                <Title text=data.post_name.clone() />
+                                                <PostHeader post=data.clone() />
+                                                {data
+                                                    .series
+                                                    .clone()
+                                                    .map(|series| {
+                                                        view! {
+                                                            <SeriesNavigator
+                                                                series
+                                                                current=params
+                                                                    .get_untracked()
+                                                                    .get("id")
+                                                                    .unwrap_or_default()
+                                                            />
+                                                        }
+                                                    })}
+                                                <div inner_html=data.html></div>
+                                                <PostNavigationView slug=params
+                                                    .get_untracked()
+                                                    .get("id")
+                                                    .unwrap_or_default() />
+                                                <CommentsSection slug=params
+                                                    .get_untracked()
+                                                    .get("id")
+                                                    .unwrap_or_default() />
+                                            </div>
+                                            <TableOfContents toc=data.toc />
                                         </div>
                                     }
                                         .into_any()
                                 }
                                 Err(e) => {
-                                    println!("Unable to get data! {e:?}");
-                                    match e {
-                                        ServerFnError::Request(_) => {
-                                            let mut outside_errors = Errors::default();
-                                            outside_errors.insert_with_default_key(AppError::NotFound);
-                                            view! { <ErrorTemplate outside_errors /> }.into_any()
-                                        }
-                                        _ => {
-                                            let mut outside_errors = Errors::default();
-                                            outside_errors
-                                                .insert_with_default_key(AppError::InternalServerError);
-                                            view! { <ErrorTemplate outside_errors /> }.into_any()
-                                        }
-                                    }
+                                    tracing::error!("Unable to get data! {e:?}");
+                                    let mut outside_errors = Errors::default();
+                                    outside_errors.insert_with_default_key(e);
+                                    view! { <ErrorTemplate outside_errors /> }.into_any()
                                 }
                             }
                         }
@@ -83,47 +106,114 @@ pub fn PageView() -> impl IntoView {
     }
 }
 
+#[component]
+fn PostHeader(post: PostContent) -> impl IntoView {
+    let published = post.date_published.format("%B %-d, %Y").to_string();
+    let updated = post
+        .date_updated
+        .map(|d| format!(" · Updated {}", d.format("%B %-d, %Y")));
+    view! {
+        <div class="mb-4 text-sm text-gray-500 dark:text-gray-400">
+            <span>{post.author}</span>
+            " · "
+            <time datetime=post.date_published.format("%Y-%m-%d").to_string()>{published}</time>
+            {updated}
+            " · "
+            <span>{format!("{} min read ({} words)", post.reading_time, post.word_count)}</span>
+        </div>
+    }
+}
+
+#[component]
+fn TableOfContents(toc: Vec<TocEntry>) -> impl IntoView {
+    if toc.is_empty() {
+        return "".into_any();
+    }
+    view! {
+        <aside class="hidden w-64 flex-shrink-0 lg:block">
+            <nav class="sticky top-4 max-h-screen overflow-y-auto border-l p-4 dark:border-gray-700">
+                <h4 class="mb-2 font-semibold dark:text-gray-200">"Contents"</h4>
+                <ul>
+                    {toc
+                        .into_iter()
+                        .map(|entry| {
+                            view! {
+                                <li style=format!(
+                                    "padding-left: {}rem",
+                                    entry.depth.saturating_sub(1) as f32 * 0.75,
+                                )>
+                                    <a href=format!("#{}", entry.anchor) class="text-sm">
+                                        {entry.text}
+                                    </a>
+                                </li>
+                            }
+                        })
+                        .collect::<Vec<_>>()}
+                </ul>
+            </nav>
+        </aside>
+    }
+        .into_any()
+}
+
 #[server(GetPostContent)]
-async fn get_post_content(slug: String) -> Result<(String, String), ServerFnError> {
+async fn get_post_content(slug: String, preview: Option<String>) -> Result<PostContent, AppError> {
     let state = expect_context::<Context>();
-    let (post, name) = match sqlx::query_as::<_, (String, String)>(
-        r#"
-     SELECT
-         file_path,
-         post_name
-     FROM posts
-     WHERE slug = ?;
-             "#,
-    )
-    .bind(slug)
-    .fetch_one(&state.sql_pool)
-    .await
-    {
-        Ok(p) => p,
-        Err(e) => match e {
-            sqlx::Error::RowNotFound => return Err(ServerFnError::Request("".to_string())),
-            _ => return Err(ServerFnError::ServerError(e.to_string())),
-        },
-    };
-    let file = match tokio::fs::read_to_string(post).await {
-        Ok(f) => f,
-        Err(e) => return Err(ServerFnError::ServerError(e.to_string())),
+    let post = visible_post(&state, &slug, preview.as_deref()).await?;
+    let modified = tokio::fs::metadata(&post.file_path).await?.modified()?;
+    let rendered = match state.post_cache.get(&slug, modified) {
+        Some(r) => r,
+        None => {
+            let file = tokio::fs::read_to_string(&post.file_path).await?;
+            let ext = crate::blog::render::Extensions {
+                forge_root: Some(&state.forge_dir),
+                post_dir: post.file_path.parent(),
+                trusted_html: post.trusted_html,
+                ..Default::default()
+            };
+            let rendered = match crate::blog::render::render_post(&file, &ext) {
+                Ok(r) => r,
+                Err(e) => return Err(AppError::internal(format!("{slug}: {e}"))),
+            };
+            for warning in &rendered.warnings {
+                tracing::warn!("{slug}: {warning}");
+            }
+            state
+                .post_cache
+                .insert(slug.clone(), &post.file_path, modified, rendered.clone());
+            rendered
+        }
     };
 
-    let mut parse = markdown::ParseOptions::gfm();
-    parse.constructs.block_quote = true;
-    let options = markdown::Options {
-        parse,
-        compile: markdown::CompileOptions {
-            allow_dangerous_html: true,
-            allow_dangerous_protocol: true,
-            gfm_footnote_clobber_prefix: Some("".to_string()),
-            gfm_tagfilter: true,
-            ..markdown::CompileOptions::default()
-        },
-    };
-    Ok(match markdown::to_html_with_options(&file, &options) {
-        Ok(o) => (o, name),
-        Err(e) => return Err(ServerFnError::ServerError(e.to_string())),
+    Ok(PostContent {
+        html: rendered.html,
+        post_name: post.post_name,
+        author: post
+            .author
+            .unwrap_or_else(|| super::DEFAULT_AUTHOR.to_string()),
+        date_published: post.date_published,
+        date_updated: post.date_updated,
+        toc: rendered.toc,
+        word_count: rendered.word_count,
+        reading_time: rendered.reading_time,
+        series: post.series,
     })
 }
+
+/// Looks up a post, treating drafts and scheduled posts as missing unless
+/// a valid preview token for that post was given
+#[cfg(feature = "ssr")]
+pub(crate) async fn visible_post(
+    state: &Context,
+    slug: &str,
+    preview: Option<&str>,
+) -> Result<crate::blog::source::PostRecord, AppError> {
+    let Some(post) = state.posts.post(slug).await? else {
+        return Err(AppError::NotFound);
+    };
+    if super::drafts::visible(&post, state.preview_key.as_ref(), preview) {
+        Ok(post)
+    } else {
+        Err(AppError::NotFound)
+    }
+}
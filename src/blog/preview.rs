@@ -0,0 +1,85 @@
+// Jackson Coxson
+//
+// Signed links for looking at a post before it's public.
+// A token is `<expiry unix seconds>.<hex HMAC-SHA256 of slug and expiry>`,
+// keyed with PREVIEW_SECRET. Without the secret set, drafts can't be previewed at all.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a preview link works for when no lifetime is given
+pub const DEFAULT_LIFETIME: chrono::Duration = chrono::Duration::days(7);
+
+#[derive(Clone)]
+pub struct PreviewKey {
+    secret: Vec<u8>,
+}
+
+impl PreviewKey {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("PREVIEW_SECRET") {
+            Ok(s) if !s.is_empty() => Some(Self::new(s)),
+            _ => None,
+        }
+    }
+
+    pub fn sign(&self, slug: &str, expires: i64) -> String {
+        let mac = self.mac(slug, expires).finalize().into_bytes();
+        format!("{expires}.{}", hex::encode(mac))
+    }
+
+    /// A token for `slug` that stops working after `lifetime`
+    pub fn sign_for(&self, slug: &str, lifetime: chrono::Duration) -> String {
+        self.sign(slug, (chrono::Utc::now() + lifetime).timestamp())
+    }
+
+    pub fn verify(&self, slug: &str, token: &str, now: i64) -> bool {
+        let (Some(expires), Some((_, signature))) = (expires(token), token.split_once('.')) else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        expires >= now && self.mac(slug, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, slug: &str, expires: i64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC takes keys of any size");
+        mac.update(slug.as_bytes());
+        mac.update(b"\0");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+/// When a token stops working, whether or not it's valid
+pub fn expires(token: &str) -> Option<i64> {
+    token.split_once('.')?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let key = PreviewKey::new("secret");
+        let token = key.sign("draft", 100);
+        assert!(key.verify("draft", &token, 50));
+        assert!(key.verify("draft", &token, 100));
+        assert!(!key.verify("draft", &token, 101), "expired");
+        assert!(!key.verify("other", &token, 50), "wrong post");
+        assert!(!PreviewKey::new("nope").verify("draft", &token, 50));
+        assert!(!key.verify("draft", &token.replace("100.", "200."), 50));
+        assert!(!key.verify("draft", "garbage", 50));
+    }
+}
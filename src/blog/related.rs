@@ -0,0 +1,205 @@
+// Jackson Coxson
+//
+// Previous/next links and related posts under a post.
+// Related posts are scored by the tags and category they share with the
+// current post, the newest post wins ties.
+
+#[cfg(feature = "ssr")]
+use crate::blog::source::PostRecord;
+use crate::blog::structures::{PostNavigation, PostPreview};
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+use leptos::prelude::*;
+
+#[cfg(feature = "ssr")]
+const MAX_RELATED: usize = 3;
+#[cfg(feature = "ssr")]
+const TAG_SCORE: usize = 2;
+#[cfg(feature = "ssr")]
+const CATEGORY_SCORE: usize = 1;
+
+#[component]
+pub fn PostNavigationView(slug: String) -> impl IntoView {
+    let nav = Resource::new(
+        move || slug.clone(),
+        |slug| async move { get_post_navigation(slug).await },
+    );
+    view! {
+        <Transition fallback=|| ()>
+            {move || match nav.get() {
+                Some(Ok(nav)) => {
+                    view! {
+                        <nav class="mt-12 grid grid-cols-1 gap-4 md:grid-cols-2">
+                            <div>
+                                {nav
+                                    .previous
+                                    .map(|p| view! { <PostCard preview=p label="Previous" /> })}
+                            </div>
+                            <div class="md:text-right">
+                                {nav.next.map(|p| view! { <PostCard preview=p label="Next" /> })}
+                            </div>
+                        </nav>
+                        {(!nav.related.is_empty())
+                            .then(|| {
+                                view! {
+                                    <h2 class="mt-8">"Related posts"</h2>
+                                    <div class="grid grid-cols-1 gap-4 md:grid-cols-3">
+                                        {nav
+                                            .related
+                                            .into_iter()
+                                            .map(|p| view! { <PostCard preview=p /> })
+                                            .collect::<Vec<_>>()}
+                                    </div>
+                                }
+                            })}
+                    }
+                        .into_any()
+                }
+                Some(Err(e)) => {
+                    tracing::error!("Unable to load post navigation: {e:?}");
+                    "".into_any()
+                }
+                None => "".into_any(),
+            }}
+        </Transition>
+    }
+}
+
+#[component]
+fn PostCard(preview: PostPreview, #[prop(optional)] label: Option<&'static str>) -> impl IntoView {
+    view! {
+        <a
+            href=format!("/blog/{}", preview.slug)
+            class="block h-full rounded-lg bg-gray-100 p-4 transition hover:bg-gray-200 dark:bg-gray-800 dark:hover:bg-gray-700"
+        >
+            {label.map(|l| view! { <small class="text-gray-500">{l}</small> })}
+            {preview
+                .image_path
+                .map(|i| {
+                    view! { <img src=i alt="Post Image" class="mb-2 h-32 w-full rounded object-cover" /> }
+                })}
+            <h3 class="mb-1 text-lg font-semibold">{preview.post_name}</h3>
+            <p class="mb-1 text-gray-600 dark:text-gray-200">{preview.sneak_peak}</p>
+            <small class="text-gray-500">{preview.relative_date}</small>
+        </a>
+    }
+}
+
+#[server(GetPostNavigation, "/api", "getjson", "get_post_navigation")]
+pub async fn get_post_navigation(slug: String) -> Result<PostNavigation, AppError> {
+    let state = expect_context::<Context>();
+    Ok(navigation(state.posts.live_posts().await?, &slug))
+}
+
+/// `posts` are the live posts, newest first.
+/// Drafts being previewed aren't in there, so they get nothing.
+#[cfg(feature = "ssr")]
+fn navigation(mut posts: Vec<PostRecord>, slug: &str) -> PostNavigation {
+    let Some(index) = posts.iter().position(|p| p.slug == slug) else {
+        return PostNavigation::default();
+    };
+    let current = posts.remove(index);
+
+    // After the removal, index points at the next older post
+    let previous = posts.get(index).map(|p| p.slug.clone());
+    let next = index
+        .checked_sub(1)
+        .and_then(|i| posts.get(i))
+        .map(|p| p.slug.clone());
+
+    let mut scored = posts
+        .iter()
+        .filter(|p| Some(&p.slug) != previous.as_ref() && Some(&p.slug) != next.as_ref())
+        .map(|p| (score(&current, p), p))
+        .filter(|(score, _)| *score > 0)
+        .collect::<Vec<_>>();
+    // Stable, so equal scores stay newest first
+    scored.sort_by(|a, b| b.0.cmp(&a.0));
+    let related = scored
+        .into_iter()
+        .take(MAX_RELATED)
+        .map(|(_, p)| p.slug.clone())
+        .collect::<Vec<_>>();
+
+    let take = |slug: &String| {
+        posts
+            .iter()
+            .find(|p| &p.slug == slug)
+            .cloned()
+            .map(super::browse::preview)
+    };
+    PostNavigation {
+        previous: previous.as_ref().and_then(take),
+        next: next.as_ref().and_then(take),
+        related: related.iter().filter_map(take).collect(),
+    }
+}
+
+/// File posts don't have tag ids, so names are compared
+#[cfg(feature = "ssr")]
+fn score(current: &PostRecord, other: &PostRecord) -> usize {
+    let shared_tags = current
+        .tags
+        .iter()
+        .filter(|t| {
+            other
+                .tags
+                .iter()
+                .any(|o| o.tage_name.eq_ignore_ascii_case(&t.tage_name))
+        })
+        .count();
+    let same_category = match (&current.category, &other.category) {
+        (Some(a), Some(b)) => a.category_name.eq_ignore_ascii_case(&b.category_name),
+        _ => false,
+    };
+    shared_tags * TAG_SCORE + usize::from(same_category) * CATEGORY_SCORE
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+    use crate::blog::structures::{Category, Tag};
+
+    fn post(slug: &str, day: u32, category: Option<&str>, tags: &[&str]) -> PostRecord {
+        PostRecord {
+            category: category.map(|c| Category {
+                id: 0,
+                category_name: c.to_string(),
+            }),
+            tags: tags
+                .iter()
+                .map(|t| Tag {
+                    id: 0,
+                    tage_name: t.to_string(),
+                })
+                .collect(),
+            ..PostRecord::fixture(slug, day)
+        }
+    }
+
+    #[test]
+    fn neighbours_and_related() {
+        // Newest first, like live_posts returns them
+        let posts = vec![
+            post("e", 5, None, &["rust"]),
+            post("d", 4, Some("ios"), &[]),
+            post("c", 3, Some("ios"), &["rust", "usb"]),
+            post("b", 2, None, &["python"]),
+            post("a", 1, Some("iOS"), &["Rust", "usb"]),
+        ];
+
+        let nav = navigation(posts.clone(), "c");
+        assert_eq!(nav.previous.map(|p| p.slug), Some("b".to_string()));
+        assert_eq!(nav.next.map(|p| p.slug), Some("d".to_string()));
+        let related = nav.related.into_iter().map(|p| p.slug).collect::<Vec<_>>();
+        assert_eq!(related, ["a", "e"]);
+
+        let nav = navigation(posts.clone(), "e");
+        assert!(nav.next.is_none());
+        assert_eq!(nav.previous.map(|p| p.slug), Some("d".to_string()));
+
+        let nav = navigation(posts, "missing");
+        assert!(nav.previous.is_none() && nav.next.is_none() && nav.related.is_empty());
+    }
+}
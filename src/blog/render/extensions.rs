@@ -0,0 +1,569 @@
+// Jackson Coxson
+//
+// The blog's additions to GFM, applied to the HTML markdown-rs compiles.
+//
+// > [!NOTE]                  GitHub style admonitions, also TIP, IMPORTANT, WARNING and CAUTION
+// $x^2$ and $$\sum x$$       math, converted to MathML (see math.rs)
+// {{forge:files/thing.zip}}  a /cdn/ link to a forge file, with its size
+// {{youtube:dQw4w9WgXcQ}}    an embedded YouTube video
+// ![](diagram.png)           relative links and images point into the post's forge folder
+//
+// Images also get lazy loading and their size from the file, so the page doesn't jump.
+// Links, images and shortcodes are rewritten with lol_html. Urls are kept the way
+// they're written in the HTML, escapes and all, and only unescaped to find the file.
+
+use std::{
+    cell::{Cell, RefCell},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use lol_html::{
+    doc_text, element, html_content::ContentType, rewrite_str, EndTagHandler, RewriteStrSettings,
+};
+
+use super::html::{normalize_url, unescape};
+
+const ADMONITIONS: [(&str, &str); 5] = [
+    ("NOTE", "Note"),
+    ("TIP", "Tip"),
+    ("IMPORTANT", "Important"),
+    ("WARNING", "Warning"),
+    ("CAUTION", "Caution"),
+];
+
+/// Shortcodes in these are left as written
+const NO_SHORTCODES: &str = "a, code, pre, script, style, textarea, title";
+
+/// What the extensions need to know about the outside world
+#[derive(Default)]
+pub struct Extensions<'a> {
+    /// Where forge shortcodes and /cdn/ paths are looked up, links are still made without it
+    pub forge_root: Option<&'a Path>,
+    /// The folder the post's markdown is in, relative paths resolve against it
+    pub post_dir: Option<&'a Path>,
+    /// Keep raw HTML as written, otherwise the output is sanitized (see sanitize.rs)
+    pub trusted_html: bool,
+}
+
+/// Resolves links and images and expands shortcodes,
+/// returning warnings about things that look broken
+pub fn rewrite(html: &str, ext: &Extensions) -> Result<(String, Vec<String>), String> {
+    let rewriter = RefCell::new(Rewriter {
+        ext,
+        warnings: Vec::new(),
+    });
+    // How many elements in NO_SHORTCODES the text is in
+    let skipping = Rc::new(Cell::new(0usize));
+    let mut text = String::new();
+
+    let html = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el| {
+                    let href = el.get_attribute("href").unwrap_or_default();
+                    let mut rewriter = rewriter.borrow_mut();
+                    let url = rewriter.resolve(&href);
+                    rewriter.check(&url);
+                    if url != href {
+                        el.set_attribute("href", &url)?;
+                    }
+                    Ok(())
+                }),
+                element!("img[src]", |el| {
+                    let src = el.get_attribute("src").unwrap_or_default();
+                    let mut rewriter = rewriter.borrow_mut();
+                    let url = rewriter.resolve(&src);
+                    rewriter.check(&url);
+                    if url != src {
+                        el.set_attribute("src", &url)?;
+                    }
+                    if !el.has_attribute("loading") {
+                        el.set_attribute("loading", "lazy")?;
+                    }
+                    let size = rewriter
+                        .local_file(&url)
+                        .and_then(|path| imagesize::size(path).ok());
+                    if let Some(size) = size.filter(|_| !el.has_attribute("width")) {
+                        el.set_attribute("width", &size.width.to_string())?;
+                        el.set_attribute("height", &size.height.to_string())?;
+                    }
+                    Ok(())
+                }),
+                element!(NO_SHORTCODES, |el| {
+                    if el.can_have_content() {
+                        skipping.set(skipping.get() + 1);
+                        let skipping = skipping.clone();
+                        let done: EndTagHandler = Box::new(move |_| {
+                            skipping.set(skipping.get() - 1);
+                            Ok(())
+                        });
+                        el.on_end_tag(done)?;
+                    }
+                    Ok(())
+                }),
+            ],
+            document_content_handlers: vec![doc_text!(|t| {
+                if skipping.get() > 0 {
+                    return Ok(());
+                }
+                // A text node can come in more than one chunk, a shortcode could span them
+                text.push_str(t.as_str());
+                t.remove();
+                if t.last_in_text_node() {
+                    let expanded = rewriter.borrow_mut().shortcodes(&std::mem::take(&mut text));
+                    t.replace(&expanded, ContentType::Html);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok((html, rewriter.into_inner().warnings))
+}
+
+struct Rewriter<'a, 'b> {
+    ext: &'b Extensions<'a>,
+    warnings: Vec<String>,
+}
+
+impl Rewriter<'_, '_> {
+    /// Points a relative url at the post's forge folder
+    fn resolve(&mut self, url: &str) -> String {
+        let (Some(root), Some(dir)) = (self.ext.forge_root, self.ext.post_dir) else {
+            return url.to_string();
+        };
+        if !is_relative(url) {
+            return url.to_string();
+        }
+        // Baseline database rows have paths relative to the working directory
+        let (root, dir) = (crate::blog::absolute(root), crate::blog::absolute(dir));
+        let Ok(base) = dir.strip_prefix(&root) else {
+            return url.to_string();
+        };
+
+        let split = url.find(['?', '#']).unwrap_or(url.len());
+        let (path, suffix) = url.split_at(split);
+        let mut parts = base
+            .components()
+            .map(|c| normalize_url(&c.as_os_str().to_string_lossy()))
+            .collect::<Vec<_>>();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if parts.pop().is_none() {
+                        self.warnings
+                            .push(format!("{} points outside of the forge", unescape(url)));
+                        return url.to_string();
+                    }
+                }
+                s => parts.push(s.to_string()),
+            }
+        }
+        format!("/cdn/{}{suffix}", parts.join("/"))
+    }
+
+    /// Warns about /cdn/ links to files that aren't in the forge
+    fn check(&mut self, url: &str) {
+        if url.starts_with("/cdn/")
+            && self.ext.forge_root.is_some()
+            && self.local_file(url).is_none()
+        {
+            self.warnings
+                .push(format!("{} doesn't exist in the forge", unescape(url)));
+        }
+    }
+
+    /// Where a /cdn/ url is on disk, if it's there
+    fn local_file(&self, url: &str) -> Option<PathBuf> {
+        let path = url.strip_prefix("/cdn/")?;
+        let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+        let path = percent_decode(&unescape(path));
+        let path = Path::new(&path);
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        let full = self.ext.forge_root?.join(path);
+        full.is_file().then_some(full)
+    }
+
+    /// Expands the {{name:arg}} shortcodes in a text node
+    fn shortcodes(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let inner = &rest[start + 2..start + len];
+            let expanded = inner
+                .split_once(':')
+                .and_then(|(name, arg)| self.shortcode(name.trim(), arg.trim()));
+            match expanded {
+                Some(html) => {
+                    out.push_str(&rest[..start]);
+                    out.push_str(&html);
+                }
+                None => out.push_str(&rest[..start + len + 2]),
+            }
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn shortcode(&mut self, name: &str, arg: &str) -> Option<String> {
+        match name {
+            "forge" => self.forge_link(arg),
+            "youtube" => youtube(arg),
+            _ => None,
+        }
+    }
+
+    fn forge_link(&mut self, path: &str) -> Option<String> {
+        let path = Path::new(path.trim_start_matches('/'));
+        if path.as_os_str().is_empty()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        let name = path.file_name()?.to_string_lossy().to_string();
+        let url = normalize_url(&format!("/cdn/{}", path.to_string_lossy()));
+        self.check(&url);
+
+        let size = self
+            .local_file(&url)
+            .and_then(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len());
+        let mut html = format!("<a href=\"{url}\">{name}</a>");
+        if let Some(size) = size {
+            html.push_str(&format!(
+                " <span class=\"forge-size\">({})</span>",
+                format_size(size)
+            ));
+        }
+        Some(html)
+    }
+}
+
+/// Not absolute, not a fragment and without a scheme like https: or mailto:
+fn is_relative(url: &str) -> bool {
+    if url.is_empty() || url.starts_with(['/', '#', '?']) {
+        return false;
+    }
+    match url.find(':') {
+        Some(colon) => url[..colon].contains(['/', '?', '#']),
+        None => true,
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Turns blockquotes starting with a [!KIND] line into admonitions
+pub fn admonitions(html: &str) -> String {
+    const OPEN: &str = "<blockquote>\n<p>[!";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(OPEN) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some((kind, title, paragraph, body)) = admonition(&rest[OPEN.len()..]) else {
+            out.push_str(&rest[..OPEN.len()]);
+            rest = &rest[OPEN.len()..];
+            continue;
+        };
+        let Some(len) = closing_blockquote(body) else {
+            break;
+        };
+        out.push_str(&format!(
+            "<div class=\"admonition admonition-{}\">\n<p class=\"admonition-title\">{title}</p>\n",
+            kind.to_lowercase()
+        ));
+        out.push_str(paragraph);
+        // The body can have admonitions of its own
+        out.push_str(&admonitions(&body[..len]));
+        out.push_str("</div>");
+        rest = &body[len + "</blockquote>".len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The kind, title and the HTML after the marker if the text starts with KIND] on its own line.
+/// When the paragraph carries on after the marker, the body needs a <p> put back in front.
+fn admonition(text: &str) -> Option<(&'static str, &'static str, &'static str, &str)> {
+    let (kind, after) = text.split_once(']')?;
+    let (kind, title) = ADMONITIONS
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(kind))
+        .copied()?;
+    if let Some(body) = after.strip_prefix("</p>\n") {
+        return Some((kind, title, "", body));
+    }
+    // A hard break right after the marker would otherwise start the body
+    let body = after
+        .strip_prefix("<br />\n")
+        .or_else(|| after.strip_prefix('\n'))?;
+    Some((kind, title, "<p>", body))
+}
+
+/// Where the blockquote the HTML is in ends, counting any nested in it
+fn closing_blockquote(html: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while let Some(next) = html[i..].find("blockquote") {
+        let at = i + next;
+        i = at + "blockquote".len();
+        if html[..at].ends_with("</") {
+            if depth == 0 {
+                return Some(at - 2);
+            }
+            depth -= 1;
+        } else if html[..at].ends_with('<') {
+            depth += 1;
+        }
+    }
+    None
+}
+
+fn youtube(id: &str) -> Option<String> {
+    if id.is_empty()
+        || id.len() > 64
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    Some(format!(
+        "<iframe class=\"youtube\" src=\"https://www.youtube-nocookie.com/embed/{id}\" \
+         title=\"YouTube video\" loading=\"lazy\" \
+         allow=\"accelerometer; encrypted-media; gyroscope; picture-in-picture\" \
+         allowfullscreen=\"\"></iframe>"
+    ))
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html(md: &str) -> String {
+        markdown::to_html_with_options(md, &super::super::markdown_options()).unwrap()
+    }
+
+    fn rewritten(md: &str, ext: &Extensions) -> (String, Vec<String>) {
+        rewrite(&html(md), ext).unwrap()
+    }
+
+    fn forge(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("jkcoxson-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("blog/post")).unwrap();
+        root
+    }
+
+    #[test]
+    fn admonition_blocks() {
+        assert_eq!(
+            admonitions(&html("> [!WARNING]\n> Be careful\n")),
+            "<div class=\"admonition admonition-warning\">\n\
+             <p class=\"admonition-title\">Warning</p>\n<p>Be careful</p>\n</div>\n"
+        );
+        assert_eq!(
+            admonitions(&html("> [!note]  \n> Hard break\n")),
+            "<div class=\"admonition admonition-note\">\n\
+             <p class=\"admonition-title\">Note</p>\n<p>Hard break</p>\n</div>\n"
+        );
+        let nested = admonitions(&html(
+            "> [!TIP]\n>\n> > quoted\n>\n> > [!CAUTION]\n> > inner\n\nafter\n",
+        ));
+        assert!(nested.starts_with(
+            "<div class=\"admonition admonition-tip\">\n<p class=\"admonition-title\">Tip</p>\n\
+             <blockquote>\n<p>quoted</p>\n</blockquote>\n<div class=\"admonition admonition-caution\">"
+        ));
+        assert!(
+            nested.ends_with("<p>inner</p>\n</div>\n</div>\n<p>after</p>\n"),
+            "{nested}"
+        );
+
+        // Not an admonition kind, or not on its own line
+        for md in ["> [!NOPE]\n> text\n", "> [!NOTE] inline\n", "> plain\n"] {
+            let html = html(md);
+            assert_eq!(admonitions(&html), html, "{md}");
+        }
+    }
+
+    #[test]
+    fn forge_shortcodes() {
+        let root = forge("shortcodes");
+        std::fs::create_dir_all(root.join("files")).unwrap();
+        std::fs::write(root.join("files/thing.zip"), vec![0; 1536]).unwrap();
+        let ext = Extensions {
+            forge_root: Some(&root),
+            ..Default::default()
+        };
+
+        let (html, warnings) = rewritten("Get {{forge:files/thing.zip}} here", &ext);
+        assert_eq!(
+            html,
+            "<p>Get <a href=\"/cdn/files/thing.zip\">thing.zip</a> \
+             <span class=\"forge-size\">(1.5 KB)</span> here</p>"
+        );
+        assert!(warnings.is_empty());
+
+        // Missing files still link, escapes, unknown shortcodes and code are left alone
+        let (html, warnings) = rewritten(
+            "{{forge:nope.bin}} {{forge:../etc/passwd}} {{huh:x}} `{{forge:a}}`",
+            &ext,
+        );
+        assert_eq!(
+            html,
+            "<p><a href=\"/cdn/nope.bin\">nope.bin</a> {{forge:../etc/passwd}} {{huh:x}} \
+             <code>{{forge:a}}</code></p>"
+        );
+        assert_eq!(warnings.len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn youtube_shortcodes() {
+        let (html, _) = rewritten("{{youtube:dQw4w9WgXcQ}}", &Extensions::default());
+        assert!(html.starts_with(
+            "<p><iframe class=\"youtube\" src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ\""
+        ));
+
+        let (html, _) = rewritten("{{youtube:\"><script>}}", &Extensions::default());
+        assert_eq!(html, "<p>{{youtube:&quot;&gt;&lt;script>}}</p>");
+    }
+
+    #[test]
+    fn relative_paths() {
+        let root = forge("paths");
+        let dir = root.join("blog/post");
+        std::fs::write(dir.join("notes.txt"), "hi").unwrap();
+        std::fs::write(dir.join("a&b.txt"), "hi").unwrap();
+        let ext = Extensions {
+            forge_root: Some(&root),
+            post_dir: Some(&dir),
+            ..Default::default()
+        };
+
+        let (html, warnings) = rewritten(
+            "[a](notes.txt#top) [b](./missing.txt) [c](../other/x.png) [d](https://x.com) \
+             [e](/about) [f](#h) [g](../../../etc) [h][ref] [i](a&b.txt)\n\n[ref]: notes.txt\n",
+            &ext,
+        );
+        let urls = html
+            .split("href=\"")
+            .skip(1)
+            .filter_map(|s| s.split_once('"').map(|(url, _)| url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "/cdn/blog/post/notes.txt#top",
+                "/cdn/blog/post/missing.txt",
+                "/cdn/blog/other/x.png",
+                "https://x.com",
+                "/about",
+                "#h",
+                "../../../etc",
+                "/cdn/blog/post/notes.txt",
+                "/cdn/blog/post/a&amp;b.txt",
+            ]
+        );
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("missing.txt"));
+        assert!(warnings[1].contains("other/x.png"));
+        assert!(warnings[2].contains("outside of the forge"));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        // Database rows can have a path relative to the working directory
+        let root = std::env::current_dir().unwrap().join("forge");
+        let ext = Extensions {
+            forge_root: Some(&root),
+            post_dir: Some(Path::new("forge/blog/post")),
+            ..Default::default()
+        };
+        let (html, _) = rewritten("[a](a.txt)\n", &ext);
+        assert_eq!(html, "<p><a href=\"/cdn/blog/post/a.txt\">a</a></p>\n");
+    }
+
+    #[test]
+    fn images() {
+        let root = forge("images");
+        let dir = root.join("blog/post");
+        // The smallest PNG header imagesize will read, 640x480
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        std::fs::write(dir.join("shot.png"), png).unwrap();
+        let ext = Extensions {
+            forge_root: Some(&root),
+            post_dir: Some(&dir),
+            ..Default::default()
+        };
+
+        let (html, warnings) = rewritten("![A shot](shot.png \"Title\") ![gone](gone.png)\n", &ext);
+        assert_eq!(
+            html,
+            "<p><img src=\"/cdn/blog/post/shot.png\" alt=\"A shot\" title=\"Title\" \
+             loading=\"lazy\" width=\"640\" height=\"480\" /> \
+             <img src=\"/cdn/blog/post/gone.png\" alt=\"gone\" loading=\"lazy\" /></p>\n"
+        );
+        // Missing images keep lazy loading but have no size
+        assert_eq!(warnings.len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(10), "10 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+}
@@ -0,0 +1,59 @@
+// Jackson Coxson
+//
+// Escaping shared by the passes that work on the HTML markdown-rs compiles.
+// markdown-rs escapes &, <, > and " in text and attributes, and nothing else,
+// so that's all that has to be undone to get the source text back.
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Undoes the escaping the markdown compiler applies to text and code
+pub fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Percent-encodes what can't appear in a URL, leaving existing escapes alone
+pub fn normalize_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_alphanumeric() || "!#$%&'()*+,-./:;=?@[]_~".contains(c) {
+            out.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{b:02X}"));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = "a < b & \"c\" &amp;";
+        assert_eq!(escape(text), "a &lt; b &amp; &quot;c&quot; &amp;amp;");
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(
+            normalize_url("/cdn/a b/é.png?x=1#y"),
+            "/cdn/a%20b/%C3%A9.png?x=1#y"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Jackson Coxson
+//
+// Renders the $...$ and $$...$$ in posts to MathML with KaTeX.
+// markdown-rs compiles math to code elements with a language-math class,
+// which are swapped out here. Browsers render MathML natively, so nothing
+// has to run on the client. TeX that KaTeX can't parse is left as code
+// and reported with the post's other warnings.
+
+use super::html::unescape;
+
+const INLINE: (&str, &str) = ("<code class=\"language-math math-inline\">", "</code>");
+const DISPLAY: (&str, &str) = (
+    "<pre><code class=\"language-math math-display\">",
+    "</code></pre>",
+);
+
+/// Replaces each math element with MathML, returning warnings for the ones that failed
+pub fn render_math(html: &str) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(html.len() * 2);
+    let mut warnings = Vec::new();
+    let mut rest = html;
+    loop {
+        let next = [(INLINE, false), (DISPLAY, true)]
+            .into_iter()
+            .filter_map(|(tags, display)| rest.find(tags.0).map(|i| (i, tags, display)))
+            .min_by_key(|(i, ..)| *i);
+        let Some((start, (open, close), display)) = next else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(close) else {
+            break;
+        };
+        let element = &rest[..end + close.len()];
+        rest = &rest[end + close.len()..];
+
+        let tex = unescape(&element[open.len()..end]);
+        match to_mathml(tex.trim(), display) {
+            Ok(mathml) => out.push_str(&mathml),
+            Err(e) => {
+                warnings.push(format!("Unable to render ${}$: {e}", tex.trim()));
+                out.push_str(element);
+            }
+        }
+    }
+    out.push_str(rest);
+    (out, warnings)
+}
+
+/// Converts TeX to a <math> element, wrapped in a span with the katex class
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, katex::Error> {
+    let opts = katex::Opts::builder()
+        .output_type(katex::OutputType::Mathml)
+        .display_mode(display)
+        .throw_on_error(true)
+        .build()
+        .expect("every KaTeX option is set");
+    katex::render_with_opts(tex, &opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders() {
+        let (html, warnings) = render_math(
+            "<p><code class=\"language-math math-inline\">x^2 &lt; 1</code></p>\n\
+             <pre><code class=\"language-math math-display\">\\frac{a}{b}\n</code></pre>\n\
+             <pre><code class=\"language-math\">q\n</code></pre>",
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(html.starts_with("<p><span class=\"katex\"><math"));
+        assert!(html.contains("<msup><mi>x</mi><mn>2</mn></msup><mo>&lt;</mo>"));
+        assert!(html.contains("display=\"block\""));
+        assert!(html.contains("<mfrac><mi>a</mi><mi>b</mi></mfrac>"));
+        assert!(html.contains("<annotation encoding=\"application/x-tex\">x^2 &lt; 1</annotation>"));
+        // Fenced ```math blocks are left as code
+        assert!(html.ends_with("<pre><code class=\"language-math\">q\n</code></pre>"));
+    }
+
+    #[test]
+    fn broken_tex() {
+        let code = "<code class=\"language-math math-inline\">\\nope{x}</code>";
+        let (html, warnings) = render_math(code);
+        assert_eq!(html, code);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("\\nope"), "{warnings:?}");
+    }
+}
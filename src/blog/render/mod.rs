@@ -0,0 +1,381 @@
+// Jackson Coxson
+//
+// Turns a post's markdown into the HTML and metadata served by the blog.
+// The source is parsed into an mdast once to pull out headings and count words,
+// and compiled to HTML by markdown-rs. The blog's extensions (see extensions.rs),
+// math, heading anchors and code highlighting are applied to that HTML,
+// then anything that isn't on the allowlist in sanitize.rs is stripped.
+
+use std::collections::HashMap;
+
+use markdown::mdast::Node;
+use once_cell::sync::Lazy;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use super::structures::TocEntry;
+
+pub mod extensions;
+mod html;
+mod math;
+mod sanitize;
+
+pub use extensions::Extensions;
+pub use html::escape;
+use html::unescape;
+pub use sanitize::sanitize;
+
+/// Average adult reading speed used for the reading time estimate
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Must match the prefix style/highlight.css was generated with
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Bump when the output changes in a way the markdown options don't capture
+const RENDER_VERSION: u32 = 5;
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// Identifies the pipeline a render was made with, so cached posts made
+/// with different options are never served
+pub static OPTIONS_HASH: Lazy<u64> = Lazy::new(|| {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", markdown_options()).hash(&mut hasher);
+    RENDER_VERSION.hash(&mut hasher);
+    hasher.finish()
+});
+
+#[derive(Clone)]
+pub struct RenderedPost {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub word_count: usize,
+    pub reading_time: usize,
+    /// Broken links and images found while rendering
+    pub warnings: Vec<String>,
+}
+
+/// The options every post is parsed and compiled with
+pub fn markdown_options() -> markdown::Options {
+    let mut parse = markdown::ParseOptions::gfm();
+    parse.constructs.block_quote = true;
+    // File based posts keep their metadata at the top of post.md
+    parse.constructs.frontmatter = true;
+    parse.constructs.math_flow = true;
+    parse.constructs.math_text = true;
+    markdown::Options {
+        parse,
+        compile: markdown::CompileOptions {
+            // Kept for trusted posts, everything else is sanitized after rendering
+            allow_dangerous_html: true,
+            allow_dangerous_protocol: true,
+            gfm_footnote_clobber_prefix: Some("".to_string()),
+            gfm_tagfilter: true,
+            ..markdown::CompileOptions::default()
+        },
+    }
+}
+
+pub fn render_post(source: &str, ext: &Extensions) -> Result<RenderedPost, String> {
+    let options = markdown_options();
+    let tree = markdown::to_mdast(source, &options.parse).map_err(|e| e.to_string())?;
+
+    let mut toc = Vec::new();
+    let mut slugs = HashMap::new();
+    let mut word_count = 0;
+    walk(&tree, &mut toc, &mut slugs, &mut word_count);
+
+    let html = markdown::to_html_with_options(source, &options).map_err(|e| e.to_string())?;
+    // Before math, so shortcodes in TeX are still inside code and left alone
+    let (html, mut warnings) = extensions::rewrite(&html, ext)?;
+    let (html, math_warnings) = math::render_math(&html);
+    warnings.extend(math_warnings);
+    let html = extensions::admonitions(&html);
+    let html = inject_heading_ids(&html, &toc);
+    let html = highlight_code_blocks(&html);
+    let html = if ext.trusted_html {
+        html
+    } else {
+        sanitize(&html)
+    };
+
+    Ok(RenderedPost {
+        html,
+        toc,
+        word_count,
+        reading_time: word_count.div_ceil(WORDS_PER_MINUTE).max(1),
+        warnings,
+    })
+}
+
+/// Comments get GFM without raw HTML or javascript: links, and none of the post extras.
+/// They're sanitized too, in case the markdown compiler ever lets something through.
+pub fn render_comment(source: &str) -> Result<String, String> {
+    let options = markdown::Options {
+        parse: markdown::ParseOptions::gfm(),
+        compile: markdown::CompileOptions {
+            allow_dangerous_html: false,
+            allow_dangerous_protocol: false,
+            ..markdown::CompileOptions::gfm()
+        },
+    };
+    markdown::to_html_with_options(source, &options)
+        .map(|html| sanitize(&html))
+        .map_err(|e| e.to_string())
+}
+
+/// The readable text of a post with the markdown syntax stripped, for indexing
+pub fn plain_text(source: &str) -> Result<String, String> {
+    fn collect(node: &Node, out: &mut String) {
+        match node {
+            Node::Text(t) => out.push_str(&t.value),
+            Node::InlineCode(c) => out.push_str(&c.value),
+            Node::Code(c) => out.push_str(&c.value),
+            Node::InlineMath(m) => out.push_str(&m.value),
+            Node::Math(m) => out.push_str(&m.value),
+            _ => {}
+        }
+        if let Some(children) = node.children() {
+            for child in children {
+                collect(child, out);
+            }
+        }
+        out.push(' ');
+    }
+
+    let tree = markdown::to_mdast(source, &markdown_options().parse).map_err(|e| e.to_string())?;
+    let mut out = String::with_capacity(source.len());
+    collect(&tree, &mut out);
+    Ok(out)
+}
+
+/// Collects the headings and counts the words in document order
+fn walk(
+    node: &Node,
+    toc: &mut Vec<TocEntry>,
+    slugs: &mut HashMap<String, usize>,
+    word_count: &mut usize,
+) {
+    match node {
+        Node::Heading(h) => {
+            let text = node.to_string();
+            toc.push(TocEntry {
+                depth: h.depth,
+                anchor: unique_slug(&text, slugs),
+                text,
+            });
+        }
+        Node::Text(t) => *word_count += t.value.split_whitespace().count(),
+        Node::InlineCode(c) => *word_count += c.value.split_whitespace().count(),
+        Node::Code(c) => *word_count += c.value.split_whitespace().count(),
+        _ => {}
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            walk(child, toc, slugs, word_count);
+        }
+    }
+}
+
+/// GitHub style heading slugs, so links copied from the repo still work
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c)
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn unique_slug(text: &str, slugs: &mut HashMap<String, usize>) -> String {
+    let mut slug = slugify(text);
+    if slug.is_empty() {
+        slug = "section".to_string();
+    }
+    let count = slugs.entry(slug.clone()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        slug
+    } else {
+        format!("{slug}-{}", *count - 1)
+    }
+}
+
+/// Adds an id to each compiled heading tag, matching them to the toc in order.
+/// Raw HTML headings carry their own attributes and are left alone.
+fn inject_heading_ids(html: &str, toc: &[TocEntry]) -> String {
+    let mut out = String::with_capacity(html.len() + toc.len() * 16);
+    let mut entries = toc.iter().peekable();
+    let mut rest = html;
+    while let Some(i) = rest.find("<h") {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let bytes = rest.as_bytes();
+        let depth = bytes.get(2).copied().unwrap_or_default();
+        if bytes.get(3) == Some(&b'>') && (b'1'..=b'6').contains(&depth) {
+            if let Some(entry) = entries.next_if(|e| e.depth == depth - b'0') {
+                out.push_str(&format!("<h{} id=\"{}\">", depth - b'0', entry.anchor));
+                rest = &rest[4..];
+                continue;
+            }
+        }
+        out.push_str("<h");
+        rest = &rest[2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replaces each fenced block with a known language with classed spans.
+/// Blocks we don't have a syntax for are left as plain text.
+fn highlight_code_blocks(html: &str) -> String {
+    const OPEN: &str = "<pre><code class=\"language-";
+    const CLOSE: &str = "</code></pre>";
+
+    let mut out = String::with_capacity(html.len() * 2);
+    let mut rest = html;
+    while let Some(start) = rest.find(OPEN) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(CLOSE) else {
+            break;
+        };
+        let block = &rest[..end + CLOSE.len()];
+        rest = &rest[end + CLOSE.len()..];
+
+        let (lang, code) = block[OPEN.len()..end].split_once("\">").unwrap_or_default();
+        match highlight(lang, &unescape(code)) {
+            Some(highlighted) => out.push_str(&format!(
+                "<pre class=\"hl-code\"><code class=\"language-{lang}\">{highlighted}</code></pre>"
+            )),
+            None => out.push_str(block),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn highlight(lang: &str, code: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(lang)?;
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, HIGHLIGHT_CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  `idevice` & Rust_2 "), "idevice--rust_2");
+
+        let mut seen = HashMap::new();
+        assert_eq!(unique_slug("Setup", &mut seen), "setup");
+        assert_eq!(unique_slug("Setup", &mut seen), "setup-1");
+        assert_eq!(unique_slug("???", &mut seen), "section");
+    }
+
+    #[test]
+    fn toc_and_anchors() {
+        let post = render_post(
+            "# Title\n\nsome words here\n\n## Part *one*\n\n## Part one\n",
+            &Extensions::default(),
+        )
+        .unwrap();
+        let anchors: Vec<_> = post.toc.iter().map(|e| e.anchor.as_str()).collect();
+        assert_eq!(anchors, ["title", "part-one", "part-one-1"]);
+        assert!(post.html.contains("<h2 id=\"part-one-1\">Part one</h2>"));
+        assert_eq!(post.word_count, 8);
+        assert_eq!(post.reading_time, 1);
+    }
+
+    #[test]
+    fn comments_are_escaped() {
+        let html =
+            render_comment("**hi** <script>alert(1)</script> [x](javascript:alert(1))").unwrap();
+        assert!(html.contains("<strong>hi</strong>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn extensions() {
+        let post = render_post(
+            "## Intro\n\n> [!TIP]\n> Use $x^2$\n\n{{youtube:dQw4w9WgXcQ}}\n",
+            &Extensions::default(),
+        )
+        .unwrap();
+        assert!(post.html.starts_with("<h2 id=\"intro\">Intro</h2>"));
+        assert!(post.html.contains(
+            "<div class=\"admonition admonition-tip\">\n<p class=\"admonition-title\">Tip</p>\n<p>Use <span class=\"katex\"><math"
+        ));
+        assert!(post.html.contains("<msup><mi>x</mi>"));
+        assert!(post.warnings.is_empty(), "{:?}", post.warnings);
+        assert!(post.html.contains(
+            "<iframe class=\"youtube\" src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ\""
+        ));
+        // Raw HTML in the post still goes through the tag filter
+        let post = render_post("<iframe src=x></iframe>\n", &Extensions::default()).unwrap();
+        assert!(!post.html.contains("<iframe"));
+    }
+
+    #[test]
+    fn plain_markdown_is_untouched() {
+        let trusted = Extensions {
+            trusted_html: true,
+            ..Default::default()
+        };
+        for md in [
+            "Some *em* and **strong** and ~~gone~~ with `code`.\n",
+            "- one\n- two\n  - nested\n\n3. three\n\n4. four\n",
+            "- [x] done\n- [ ] todo\n",
+            "> quoted\n> text\n\n---\n",
+            "| a | b |\n|:--|--:|\n| 1 | 2 |\n",
+            "[link](https://example.com \"Title\") and [ref][r]\n\n[r]: /r\n",
+            "Hi[^a] and[^a].\n\n[^a]: Note\n",
+            "line  \nbreak <b>html</b> {{not:a shortcode}}\n\n<div>\nblock\n</div>\n",
+            "a < b & \"c\"\n",
+        ] {
+            let expected = markdown::to_html_with_options(md, &markdown_options()).unwrap();
+            assert_eq!(render_post(md, &trusted).unwrap().html, expected, "{md}");
+        }
+    }
+
+    #[test]
+    fn highlighting() {
+        let post = render_post(
+            "```rust\nfn main() { let a = \"<b>\"; }\n```\n\n```nope\nplain\n```\n",
+            &Extensions::default(),
+        )
+        .unwrap();
+        assert!(post
+            .html
+            .contains("<pre class=\"hl-code\"><code class=\"language-rust\">"));
+        assert!(post.html.contains("hl-keyword"));
+        assert!(post.html.contains("&lt;b&gt;"));
+        assert!(!post.html.contains("<b>"));
+        assert!(post
+            .html
+            .contains("<pre><code class=\"language-nope\">plain\n</code></pre>"));
+    }
+}
@@ -0,0 +1,233 @@
+// Jackson Coxson
+//
+// Everything rendered for a page goes through an allowlist before it's injected with inner_html.
+// The allowlist covers what markdown and the blog's extensions produce: GFM, heading anchors,
+// highlighted code, footnotes, admonitions, MathML, lazy images and YouTube embeds.
+// Posts marked as trusted skip this and keep whatever raw HTML they were written with.
+
+use std::collections::{HashMap, HashSet};
+
+use ammonia::Builder;
+use once_cell::sync::Lazy;
+
+const TEXT_TAGS: &str =
+    "a abbr b blockquote br cite code del details dfn div em figcaption figure \
+    h1 h2 h3 h4 h5 h6 hr i img ins kbd li mark ol p pre q s samp section small span strong sub \
+    summary sup u ul var";
+
+/// Tables, task list checkboxes and embeds (see IFRAME_SOURCES)
+const OTHER_TAGS: &str = "table tbody td tfoot th thead tr input iframe";
+
+/// Everything KaTeX produces for MathML output (see math.rs)
+const MATHML_TAGS: &str = "math semantics annotation menclose merror mfrac mi mn mo mover \
+    mpadded mphantom mroot mrow mspace msqrt mstyle msub msubsup msup mtable mtd mtext mtr \
+    munder munderover";
+
+const GENERIC_ATTRIBUTES: &str = "class id title lang";
+
+const TAG_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("img", "src alt loading width height srcset"),
+    ("input", "type checked disabled"),
+    ("td", "align"),
+    ("th", "align"),
+    ("ol", "start"),
+    ("details", "open"),
+    ("iframe", "src allow allowfullscreen loading width height"),
+    ("math", "display xmlns"),
+    ("annotation", "encoding"),
+    ("menclose", "notation"),
+    ("mfrac", "linethickness"),
+    ("mi", "mathvariant"),
+    ("mn", "mathvariant"),
+    (
+        "mo",
+        "fence stretchy separator lspace rspace minsize maxsize largeop movablelimits",
+    ),
+    ("mover", "accent"),
+    ("mpadded", "width height depth lspace voffset"),
+    ("mrow", "mathvariant"),
+    ("mspace", "width linebreak"),
+    ("mstyle", "scriptlevel displaystyle mathcolor mathsize"),
+    (
+        "mtable",
+        "rowspacing columnalign columnspacing columnlines rowlines",
+    ),
+    ("mtd", "columnalign"),
+    ("mtext", "mathvariant"),
+    ("munder", "accentunder"),
+    ("munderover", "accent accentunder"),
+];
+
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Embeds are only allowed from places that can't run script on this origin
+const IFRAME_SOURCES: &[&str] = &["https://www.youtube-nocookie.com/embed/"];
+
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::empty();
+    builder
+        .tags(
+            [TEXT_TAGS, OTHER_TAGS, MATHML_TAGS]
+                .iter()
+                .flat_map(|tags| tags.split_whitespace())
+                .collect(),
+        )
+        .clean_content_tags(HashSet::from(["script", "style"]))
+        .generic_attributes(GENERIC_ATTRIBUTES.split_whitespace().collect())
+        // Footnotes use data-footnote-ref, aria-describedby and friends
+        .generic_attribute_prefixes(HashSet::from(["data-", "aria-"]))
+        .tag_attributes(
+            TAG_ATTRIBUTES
+                .iter()
+                .map(|(tag, attributes)| (*tag, attributes.split_whitespace().collect()))
+                .collect::<HashMap<_, _>>(),
+        )
+        .url_schemes(URL_SCHEMES.iter().copied().collect())
+        .link_rel(Some("noopener noreferrer"))
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("iframe", "src") => IFRAME_SOURCES
+                .iter()
+                .any(|s| value.starts_with(s))
+                .then_some(value.into()),
+            ("input", "type") => (value == "checkbox").then_some(value.into()),
+            // ammonia only checks the schemes of attributes holding a single url
+            ("img", "srcset") => value
+                .split(',')
+                .filter_map(|candidate| candidate.split_whitespace().next())
+                .all(allowed_url)
+                .then_some(value.into()),
+            _ => Some(value.into()),
+        });
+    builder
+});
+
+/// Relative, or using one of URL_SCHEMES
+fn allowed_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => {
+            URL_SCHEMES.iter().any(|s| s.eq_ignore_ascii_case(scheme))
+        }
+        _ => true,
+    }
+}
+
+/// Strips anything that isn't on the allowlist, leaving what the renderer produces untouched
+pub fn sanitize(html: &str) -> String {
+    SANITIZER.clean(html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blog::render::{render_post, Extensions};
+
+    /// Each of these must come out without anything that can run script
+    const XSS: &[&str] = &[
+        "<script>alert(1)</script>",
+        "<img src=x onerror=alert(1)>",
+        "<a href=\"javascript:alert(1)\">x</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">x</a>",
+        "<a href=\"&#106;avascript:alert(1)\">x</a>",
+        "<a href=\"data:text/html,<script>alert(1)</script>\">x</a>",
+        "<svg onload=alert(1)>",
+        "<svg><script>alert(1)</script></svg>",
+        "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)></style></mglyph></table></mtext></math>",
+        "<iframe src=\"javascript:alert(1)\"></iframe>",
+        "<iframe src=\"https://evil.example/\"></iframe>",
+        "<iframe srcdoc=\"<script>alert(1)</script>\"></iframe>",
+        "<div style=\"background:url(javascript:alert(1))\">x</div>",
+        "<object data=\"x.swf\"></object>",
+        "<embed src=\"x.swf\">",
+        "<form action=\"javascript:alert(1)\"><button>x</button></form>",
+        "<input type=\"image\" src=x onerror=alert(1)>",
+        "<details open ontoggle=alert(1)>",
+        "<body onload=alert(1)>",
+        "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+        "<base href=\"https://evil.example/\">",
+        "<link rel=stylesheet href=\"https://evil.example/x.css\">",
+        "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\"></noscript>",
+        "[x](javascript:alert(1))",
+        "![x](javascript:alert(1))",
+        "<img src=\"x\" srcset=\"javascript:alert(1) 1x\">",
+    ];
+
+    const DANGEROUS: &str = "script javascript: onerror onload ontoggle srcdoc style object embed \
+        form meta base link evil.example data:";
+
+    /// Escaped text is harmless, so only the tags themselves are checked
+    fn dangerous(html: &str) -> Option<&'static str> {
+        let tags = html
+            .split('<')
+            .skip(1)
+            .filter_map(|t| t.split_once('>').map(|(tag, _)| tag.to_lowercase()))
+            .collect::<Vec<_>>();
+        DANGEROUS
+            .split_whitespace()
+            .find(|bad| tags.iter().any(|t| t.contains(bad)))
+    }
+
+    #[test]
+    fn xss_vectors() {
+        for vector in XSS {
+            // Raw, and the way a post would produce it
+            let rendered = render_post(vector, &Extensions::default()).unwrap().html;
+            for html in [vector.to_string(), rendered] {
+                let clean = sanitize(&html);
+                if let Some(bad) = dangerous(&clean) {
+                    panic!("{vector} kept {bad}: {clean}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_rendered_posts() {
+        let md = "\
+# Title
+
+> [!TIP]
+> A `tip` with [a link](https://jkcoxson.com)
+
+- [x] done
+
+| a | b |
+|:--|--:|
+| 1 | 2 |
+
+Math $x^2$, $$\\sum_{i=0}^n \\hat{x}_i$$ and a note[^1]
+
+```rust
+fn main() {}
+```
+
+{{youtube:dQw4w9WgXcQ}}
+
+![alt](/cdn/x.png \"t\")
+
+[^1]: Footnote
+";
+        let html = render_post(md, &Extensions::default()).unwrap().html;
+        let clean = sanitize(&html);
+        for kept in [
+            "<h1 id=\"title\">",
+            "class=\"admonition admonition-tip\"",
+            "<input type=\"checkbox\" disabled=\"\" checked=\"\">",
+            "align=\"right\"",
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">",
+            "<msup>",
+            "<mo>∑</mo>",
+            "<mover accent=\"true\">",
+            "<annotation encoding=\"application/x-tex\">",
+            "data-footnote-ref=\"\"",
+            "aria-describedby=\"footnote-label\"",
+            "<li id=\"fn-1\">",
+            "class=\"hl-",
+            "src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ\"",
+            "loading=\"lazy\"",
+            "href=\"https://jkcoxson.com\" rel=\"noopener noreferrer\"",
+        ] {
+            assert!(clean.contains(kept), "lost {kept} from {clean}");
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Jackson Coxson
+
+use crate::app::{Footer, NavBar};
+use crate::blog::browse::{PostPreviewComponent, SearchBox};
+use crate::blog::structures::SearchResult;
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+use crate::error_template::ErrorTemplate;
+use leptos::prelude::*;
+use leptos_meta::Title;
+use leptos_router::hooks::use_query_map;
+
+/// Most results a single search will return
+const RESULT_LIMIT: usize = 20;
+
+#[component]
+pub fn SearchView() -> impl IntoView {
+    let query = use_query_map();
+    let q = move || query.get().get("q").unwrap_or_default();
+    let results = Resource::new(q, |q| async move { search_posts(q).await });
+
+    view! {
+        <NavBar />
+        <Title text="Blog Search" />
+        <div class="flex justify-center">
+            <div class="m-6 flex w-5/6 flex-col md:w-3/4">
+                <h1 class="m-6">"Search"</h1>
+                {move || view! { <SearchBox query=q() /> }}
+                <hr />
+                <Suspense fallback=move || {
+                    view! { <h2>"Loading..."</h2> }
+                }>
+                    {move || match results.get() {
+                        Some(Ok(results)) if results.is_empty() => {
+                            view! { <p class="m-6">"No posts matched your search."</p> }.into_any()
+                        }
+                        Some(Ok(results)) => {
+                            view! {
+                                <div>
+                                    {results
+                                        .into_iter()
+                                        .map(|r| {
+                                            view! {
+                                                <PostPreviewComponent
+                                                    preview=r.preview
+                                                    snippet=r.snippet
+                                                />
+                                            }
+                                        })
+                                        .collect::<Vec<_>>()}
+                                </div>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => {
+                            tracing::error!("Error searching posts: {e:?}");
+                            let mut outside_errors = Errors::default();
+                            outside_errors.insert_with_default_key(e);
+                            view! { <ErrorTemplate outside_errors /> }.into_any()
+                        }
+                        None => view! { <h2>"Loading..."</h2> }.into_any(),
+                    }}
+                </Suspense>
+            </div>
+        </div>
+        <br />
+        <Footer />
+    }
+}
+
+#[server(SearchPosts, "/api", "getjson", "search_posts")]
+pub async fn search_posts(q: String) -> Result<Vec<SearchResult>, AppError> {
+    let state = expect_context::<Context>();
+    let q = q.trim().to_string();
+    if q.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if state.search_index.needs_rebuild() {
+        rebuild_search_index(&state).await?;
+    }
+    let hits = match state.search_index.search(&q, RESULT_LIMIT) {
+        Ok(h) => h,
+        Err(e) => return Err(AppError::internal(e)),
+    };
+
+    let mut previews = super::browse::query_previews(&state, None, None).await?;
+    Ok(hits
+        .into_iter()
+        .filter_map(|hit| {
+            let i = previews.iter().position(|p| p.slug == hit.slug)?;
+            Some(SearchResult {
+                preview: previews.swap_remove(i),
+                snippet: hit.snippet,
+            })
+        })
+        .collect())
+}
+
+/// Reads every live post back off disk and rebuilds the index from them
+#[cfg(feature = "ssr")]
+async fn rebuild_search_index(state: &Context) -> Result<(), AppError> {
+    use super::search_index::IndexedPost;
+
+    let records = state.posts.live_posts().await?;
+
+    let mut posts = Vec::with_capacity(records.len());
+    for post in records {
+        let body = match tokio::fs::read_to_string(&post.file_path).await {
+            Ok(f) => super::render::plain_text(&f).unwrap_or(f),
+            Err(e) => {
+                tracing::warn!(
+                    "Unable to read post {} for the search index: {e:?}",
+                    post.slug
+                );
+                String::new()
+            }
+        };
+        posts.push(IndexedPost {
+            slug: post.slug,
+            title: post.post_name,
+            sneak_peak: post.sneak_peak,
+            tags: post.tags.into_iter().map(|t| t.tage_name).collect(),
+            body,
+        });
+    }
+
+    let index = state.search_index.clone();
+    match tokio::task::spawn_blocking(move || index.rebuild(posts)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(AppError::internal(e)),
+        Err(e) => Err(AppError::internal(e)),
+    }
+}
@@ -0,0 +1,225 @@
+// Jackson Coxson
+//
+// In-memory full-text index over the blog.
+// The whole thing is rebuilt from scratch whenever it goes stale, there aren't
+// nearly enough posts for incremental updates to be worth it.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use tantivy::{
+    collector::TopDocs,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value, STORED, STRING, TEXT},
+    snippet::SnippetGenerator,
+    Index, IndexReader, TantivyDocument,
+};
+
+/// Rows can change without the forge noticing, so rebuild every so often anyway
+const MAX_AGE: Duration = Duration::from_secs(10 * 60);
+const SNIPPET_CHARS: usize = 200;
+
+pub struct IndexedPost {
+    pub slug: String,
+    pub title: String,
+    pub sneak_peak: Option<String>,
+    pub tags: Vec<String>,
+    pub body: String,
+}
+
+pub struct SearchHit {
+    pub slug: String,
+    pub snippet: String,
+}
+
+struct Built {
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+    built_at: Instant,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    slug: Field,
+    title: Field,
+    sneak_peak: Field,
+    tags: Field,
+    body: Field,
+}
+
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    inner: Arc<RwLock<Option<Built>>>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl SearchIndex {
+    /// Called by the forge watcher when any file changes
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    pub fn needs_rebuild(&self) -> bool {
+        if self.dirty.load(Ordering::SeqCst) {
+            return true;
+        }
+        match self.inner.read().unwrap().as_ref() {
+            Some(b) => b.built_at.elapsed() > MAX_AGE,
+            None => true,
+        }
+    }
+
+    pub fn rebuild(&self, posts: Vec<IndexedPost>) -> tantivy::Result<()> {
+        // Clear the flag first so changes that land mid-build trigger another one
+        self.dirty.store(false, Ordering::SeqCst);
+
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            slug: builder.add_text_field("slug", STRING | STORED),
+            title: builder.add_text_field("title", TEXT | STORED),
+            sneak_peak: builder.add_text_field("sneak_peak", TEXT | STORED),
+            tags: builder.add_text_field("tags", TEXT | STORED),
+            body: builder.add_text_field("body", TEXT | STORED),
+        };
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 15_000_000)?;
+        for post in posts {
+            writer.add_document(doc!(
+                fields.slug => post.slug,
+                fields.title => post.title,
+                fields.sneak_peak => post.sneak_peak.unwrap_or_default(),
+                fields.tags => post.tags.join(" "),
+                fields.body => post.body,
+            ))?;
+        }
+        writer.commit()?;
+        let reader = index.reader()?;
+
+        *self.inner.write().unwrap() = Some(Built {
+            index,
+            reader,
+            fields,
+            built_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Returns the best matching slugs in order, with the matching part of the
+    /// post as HTML where the hits are wrapped in `<b>`
+    pub fn search(&self, query: &str, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        let inner = self.inner.read().unwrap();
+        let Some(built) = inner.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let fields = built.fields;
+
+        let mut parser = QueryParser::for_index(
+            &built.index,
+            vec![fields.title, fields.sneak_peak, fields.tags, fields.body],
+        );
+        parser.set_field_boost(fields.title, 3.0);
+        parser.set_field_boost(fields.tags, 2.0);
+        parser.set_field_boost(fields.sneak_peak, 1.5);
+        let (query, _) = parser.parse_query_lenient(query);
+
+        let searcher = built.reader.searcher();
+        let top = searcher.search(&query, &TopDocs::with_limit(limit))?;
+        let mut body_snippets = SnippetGenerator::create(&searcher, &*query, fields.body)?;
+        body_snippets.set_max_num_chars(SNIPPET_CHARS);
+        let mut peak_snippets = SnippetGenerator::create(&searcher, &*query, fields.sneak_peak)?;
+        peak_snippets.set_max_num_chars(SNIPPET_CHARS);
+
+        let mut hits = Vec::with_capacity(top.len());
+        for (_score, address) in top {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let slug = doc
+                .get_first(fields.slug)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+
+            // Prefer a hit in the body, then the blurb, then just the start of the blurb
+            let mut snippet = body_snippets.snippet_from_doc(&doc);
+            if snippet.highlighted().is_empty() {
+                snippet = peak_snippets.snippet_from_doc(&doc);
+            }
+            let snippet = if snippet.fragment().is_empty() {
+                doc.get_first(fields.sneak_peak)
+                    .and_then(|v| v.as_str())
+                    .map(escape)
+                    .unwrap_or_default()
+            } else {
+                snippet.to_html()
+            };
+            hits.push(SearchHit { slug, snippet });
+        }
+        Ok(hits)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(slug: &str, title: &str, tags: &[&str], body: &str) -> IndexedPost {
+        IndexedPost {
+            slug: slug.to_string(),
+            title: title.to_string(),
+            sneak_peak: Some(format!("All about {title} <3")),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn ranked_with_snippets() {
+        let index = SearchIndex::default();
+        assert!(index.needs_rebuild());
+        index
+            .rebuild(vec![
+                post(
+                    "usb",
+                    "WebUSB in the browser",
+                    &["web"],
+                    "Talking to an iPhone over usb.",
+                ),
+                post(
+                    "jit",
+                    "JIT on iOS",
+                    &["ios"],
+                    "Enabling JIT needs a debugger attached.",
+                ),
+                post("misc", "Misc", &["iphone"], "Nothing to see here."),
+            ])
+            .unwrap();
+        assert!(!index.needs_rebuild());
+
+        let hits = index.search("jit", 10).unwrap();
+        assert_eq!(hits[0].slug, "jit");
+        assert!(hits[0].snippet.contains("<b>JIT</b>"));
+
+        // Tag-only matches fall back to the escaped blurb
+        let hits = index.search("iphone", 10).unwrap();
+        let slugs: Vec<_> = hits.iter().map(|h| h.slug.as_str()).collect();
+        assert!(slugs.contains(&"misc") && slugs.contains(&"usb"));
+        let misc = hits.iter().find(|h| h.slug == "misc").unwrap();
+        assert_eq!(misc.snippet, "All about Misc &lt;3");
+
+        index.mark_dirty();
+        assert!(index.needs_rebuild());
+    }
+}
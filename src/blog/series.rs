@@ -0,0 +1,196 @@
+// Jackson Coxson
+//
+// Multi-part write-ups.
+// A post joins a series by naming it in its metadata, along with its part number.
+// Series don't have their own table, they're whatever the live posts say they are,
+// and are addressed at /blog/series/<series_slug(name)>.
+
+use crate::app::{Footer, NavBar};
+use crate::blog::browse::PostPreviewComponent;
+#[cfg(feature = "ssr")]
+use crate::blog::source::PostRecord;
+use crate::blog::structures::{Series, SeriesInfo};
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+use crate::error_template::ErrorTemplate;
+use leptos::prelude::*;
+use leptos_meta::Title;
+use leptos_router::hooks::use_params_map;
+
+/// The URL form of a series name, "Reversing the iPhone!" is reversing-the-iphone
+pub fn series_slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[component]
+pub fn SeriesView() -> impl IntoView {
+    let params = use_params_map();
+    let series = Resource::new(
+        move || params.get().get("name").unwrap_or_default(),
+        |name| async move { get_series(name).await },
+    );
+    view! {
+        <NavBar />
+        <div class="flex justify-center">
+            <div class="m-6 flex w-5/6 flex-col md:w-3/4">
+                <Suspense fallback=move || {
+                    view! { <h2>"Loading..."</h2> }
+                }>
+                    {move || match series.get() {
+                        Some(Ok(series)) => {
+                            view! {
+                                <Title text=series.name.clone() />
+                                <h1 class="m-6">{series.name}</h1>
+                                <hr />
+                                <div>
+                                    {series
+                                        .posts
+                                        .into_iter()
+                                        .map(|p| view! { <PostPreviewComponent preview=p /> })
+                                        .collect::<Vec<_>>()}
+                                </div>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => {
+                            tracing::error!("Error fetching series: {e:?}");
+                            let mut outside_errors = Errors::default();
+                            outside_errors.insert_with_default_key(e);
+                            view! { <ErrorTemplate outside_errors /> }.into_any()
+                        }
+                        None => view! { <h2>"Loading..."</h2> }.into_any(),
+                    }}
+                </Suspense>
+            </div>
+        </div>
+        <br />
+        <Footer />
+    }
+}
+
+/// Lists every part of the series the current post belongs to
+#[component]
+pub fn SeriesNavigator(series: SeriesInfo, current: String) -> impl IntoView {
+    let slug = series_slug(&series.name);
+    let fetch_slug = slug.clone();
+    let parts = Resource::new(
+        || (),
+        move |_| {
+            let slug = fetch_slug.clone();
+            async move { get_series(slug).await }
+        },
+    );
+    view! {
+        <aside class="my-6 rounded-lg border p-4 dark:border-gray-700">
+            <p class="mb-2 text-sm text-gray-500">
+                "This post is part of a series: "
+                <a href=format!("/blog/series/{slug}") class="font-semibold">
+                    {series.name}
+                </a>
+            </p>
+            <Transition fallback=|| ()>
+                <ol class="list-decimal pl-6">
+                    {move || {
+                        parts
+                            .get()
+                            .and_then(Result::ok)
+                            .map(|s| s.posts)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|p| {
+                                if p.slug == current {
+                                    view! { <li class="font-semibold">{p.post_name}</li> }.into_any()
+                                } else {
+                                    view! {
+                                        <li>
+                                            <a href=format!("/blog/{}", p.slug)>{p.post_name}</a>
+                                        </li>
+                                    }
+                                        .into_any()
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    }}
+                </ol>
+            </Transition>
+        </aside>
+    }
+}
+
+#[server(GetSeries, "/api", "getjson", "get_series")]
+pub async fn get_series(name: String) -> Result<Series, AppError> {
+    let state = expect_context::<Context>();
+    let parts = parts(state.posts.live_posts().await?, &name);
+    let Some(series_name) = parts
+        .first()
+        .and_then(|p| p.series.as_ref())
+        .map(|s| s.name.clone())
+    else {
+        return Err(AppError::NotFound);
+    };
+    Ok(Series {
+        name: series_name,
+        posts: parts.into_iter().map(super::browse::preview).collect(),
+    })
+}
+
+/// The posts in the series with the given slug, numbered parts first
+#[cfg(feature = "ssr")]
+fn parts(posts: Vec<PostRecord>, slug: &str) -> Vec<PostRecord> {
+    let mut parts = posts
+        .into_iter()
+        .filter(|p| {
+            p.series
+                .as_ref()
+                .is_some_and(|s| series_slug(&s.name) == slug)
+        })
+        .collect::<Vec<_>>();
+    parts.sort_by_key(|p| {
+        let part = p.series.as_ref().and_then(|s| s.part);
+        (part.is_none(), part, p.date_published)
+    });
+    parts
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    fn post(slug: &str, day: u32, series: Option<(&str, Option<i32>)>) -> PostRecord {
+        PostRecord {
+            series: series.map(|(name, part)| SeriesInfo {
+                name: name.to_string(),
+                part,
+            }),
+            ..PostRecord::fixture(slug, day)
+        }
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(series_slug("Reversing the iPhone!"), "reversing-the-iphone");
+        assert_eq!(series_slug("  USB -- part/2 "), "usb-part-2");
+    }
+
+    #[test]
+    fn ordering() {
+        let posts = vec![
+            post("loose-late", 9, Some(("USB Deep Dive", None))),
+            post("two", 8, Some(("USB Deep Dive", Some(2)))),
+            post("other", 7, Some(("Something Else", Some(1)))),
+            post("loose-early", 6, Some(("usb deep dive", None))),
+            post("one", 5, Some(("USB Deep Dive", Some(1)))),
+            post("standalone", 4, None),
+        ];
+        let slugs = parts(posts, "usb-deep-dive")
+            .into_iter()
+            .map(|p| p.slug)
+            .collect::<Vec<_>>();
+        assert_eq!(slugs, ["one", "two", "loose-early", "loose-late"]);
+    }
+}
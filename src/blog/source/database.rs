@@ -0,0 +1,35 @@
+// Jackson Coxson
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::{PostRecord, PostSource, SourceError};
+use crate::db::Repository;
+
+/// Posts stored as rows in the `posts` table, whichever database that's in
+pub struct DatabasePosts {
+    db: Arc<dyn Repository>,
+}
+
+impl DatabasePosts {
+    pub fn new(db: Arc<dyn Repository>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl PostSource for DatabasePosts {
+    async fn posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        Ok(self.db.posts().await?)
+    }
+
+    /// The database compares dates itself, in UTC rather than the server's zone
+    async fn live_posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        Ok(self.db.live_posts().await?)
+    }
+
+    async fn post(&self, slug: &str) -> Result<Option<PostRecord>, SourceError> {
+        Ok(self.db.post(slug).await?)
+    }
+}
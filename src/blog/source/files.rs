@@ -0,0 +1,314 @@
+// Jackson Coxson
+//
+// Posts that live entirely in the forge, no database needed.
+// Each folder in forge/blog is a post, the folder name is the slug.
+// The metadata is read from post.toml if there is one, otherwise from
+// YAML (---) or TOML (+++) front matter at the top of post.md.
+//
+// title = "My post"
+// date = 2024-05-01
+// updated = 2024-06-01T12:00:00Z   # optional
+// description = "Shown in the previews"
+// tags = ["rust", "ios"]
+// category = "Projects"
+// image = "/cdn/blog/my-post/header.png"
+// author = "Jackson Coxson"        # optional
+// published = true                 # defaults to true
+// series = "Reversing the iPhone"  # optional, for multi-part posts
+// series_part = 2                  # optional, the order within the series
+// trusted_html = true              # optional, keeps raw HTML like <script> as written
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+
+use super::{PostRecord, PostSource, SourceError};
+use crate::blog::{
+    absolute,
+    structures::{Category, SeriesInfo, Tag},
+};
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: String,
+    pub date: Option<String>,
+    pub updated: Option<String>,
+    #[serde(alias = "sneak_peak")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+    pub image: Option<String>,
+    pub author: Option<String>,
+    #[serde(default = "d_true")]
+    pub published: bool,
+    pub series: Option<String>,
+    pub series_part: Option<i32>,
+    #[serde(default)]
+    pub trusted_html: bool,
+}
+
+/// Indexes forge/blog in memory, kept up to date by the forge watcher
+#[derive(Clone)]
+pub struct FilePosts {
+    root: PathBuf,
+    posts: Arc<RwLock<HashMap<String, PostRecord>>>,
+}
+
+impl FilePosts {
+    /// Loads every post under `root`, which doesn't need to exist yet
+    pub fn new(root: PathBuf) -> Self {
+        let posts = Self {
+            root,
+            posts: Arc::default(),
+        };
+        posts.reload();
+        posts
+    }
+
+    pub fn reload(&self) {
+        let loaded = match load_dir(&self.root) {
+            Ok(l) => l,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Unable to load posts from {:?}: {e:?}", self.root);
+                }
+                HashMap::new()
+            }
+        };
+        *self.posts.write().unwrap() = loaded;
+    }
+
+    /// Reloads if any of the changed paths are inside the blog folder
+    pub fn handle_change(&self, paths: &[PathBuf]) {
+        let root = absolute(&self.root);
+        if paths.iter().any(|p| absolute(p).starts_with(&root)) {
+            self.reload();
+        }
+    }
+}
+
+#[async_trait]
+impl PostSource for FilePosts {
+    async fn posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        Ok(self.posts.read().unwrap().values().cloned().collect())
+    }
+
+    async fn post(&self, slug: &str) -> Result<Option<PostRecord>, SourceError> {
+        Ok(self.posts.read().unwrap().get(slug).cloned())
+    }
+}
+
+fn load_dir(root: &Path) -> Result<HashMap<String, PostRecord>, std::io::Error> {
+    let mut posts = HashMap::new();
+    for entry in std::fs::read_dir(root)? {
+        let path = entry?.path();
+        if !path.is_dir() || !path.join("post.md").is_file() {
+            continue;
+        }
+        let slug = match path.file_name() {
+            Some(s) => s.to_string_lossy().to_string(),
+            None => continue,
+        };
+        match load_post(&slug, &path) {
+            Ok(Some(p)) => {
+                posts.insert(slug, p);
+            }
+            // Database posts keep their markdown here too, without any metadata
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Skipping post {slug}: {e}"),
+        }
+    }
+    Ok(posts)
+}
+
+/// Reads a post's metadata, or None if the folder doesn't have any
+fn load_post(slug: &str, dir: &Path) -> Result<Option<PostRecord>, String> {
+    let file_path = dir.join("post.md");
+    let meta = match std::fs::read_to_string(dir.join("post.toml")) {
+        Ok(t) => parse_toml(&t)?,
+        Err(_) => {
+            let md = std::fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
+            match parse_front_matter(&md)? {
+                Some(m) => m,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let date_published = match &meta.date {
+        Some(d) => parse_date(d).ok_or(format!("unreadable date {d:?}"))?,
+        None => {
+            let modified = std::fs::metadata(&file_path)
+                .and_then(|m| m.modified())
+                .map_err(|e| e.to_string())?;
+            DateTime::<chrono::Utc>::from(modified).naive_utc()
+        }
+    };
+
+    Ok(Some(PostRecord {
+        slug: slug.to_string(),
+        post_name: meta.title,
+        file_path,
+        sneak_peak: meta.description,
+        image_path: meta.image,
+        author: meta.author,
+        published: meta.published,
+        date_published,
+        date_updated: meta.updated.as_deref().and_then(parse_date),
+        category: meta.category.map(|category_name| Category {
+            id: 0,
+            category_name,
+        }),
+        tags: meta
+            .tags
+            .into_iter()
+            .map(|tage_name| Tag { id: 0, tage_name })
+            .collect(),
+        series: meta.series.map(|name| SeriesInfo {
+            name,
+            part: meta.series_part,
+        }),
+        trusted_html: meta.trusted_html,
+    }))
+}
+
+/// Reads the front matter block at the very top of a post, if it has one
+pub fn parse_front_matter(md: &str) -> Result<Option<FrontMatter>, String> {
+    let md = md.trim_start_matches('\u{feff}');
+    for (fence, is_toml) in [("---", false), ("+++", true)] {
+        let Some(rest) = md.strip_prefix(fence) else {
+            continue;
+        };
+        let Some(rest) = rest.strip_prefix('\n').or(rest.strip_prefix("\r\n")) else {
+            continue;
+        };
+        let end = rest
+            .match_indices(fence)
+            .find(|(i, _)| *i == 0 || rest[..*i].ends_with('\n'))
+            .map(|(i, _)| i)
+            .ok_or("front matter is never closed")?;
+        let block = &rest[..end];
+        return if is_toml {
+            parse_toml(block).map(Some)
+        } else {
+            serde_yaml::from_str(block)
+                .map(Some)
+                .map_err(|e| e.to_string())
+        };
+    }
+    Ok(None)
+}
+
+/// TOML has real datetimes, which we want as plain strings like YAML gives us
+fn parse_toml(s: &str) -> Result<FrontMatter, String> {
+    let mut table: toml::Table = toml::from_str(s).map_err(|e| e.to_string())?;
+    for (_, value) in table.iter_mut() {
+        if let toml::Value::Datetime(d) = value {
+            *value = toml::Value::String(d.to_string());
+        }
+    }
+    FrontMatter::deserialize(toml::Value::Table(table)).map_err(|e| e.to_string())
+}
+
+fn parse_date(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    if let Ok(d) = DateTime::parse_from_rfc3339(s) {
+        return Some(d.naive_utc());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(d) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(d);
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+}
+
+fn d_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_matter() {
+        let yaml = "---\ntitle: Hello\ndate: 2024-05-01\ntags: [rust, ios]\npublished: false\n---\n# Hello\n";
+        let meta = parse_front_matter(yaml).unwrap().unwrap();
+        assert_eq!(meta.title, "Hello");
+        assert_eq!(meta.tags, ["rust", "ios"]);
+        assert!(!meta.published);
+
+        let toml =
+            "+++\ntitle = \"Hi\"\ndate = 2024-05-01T10:00:00Z\ncategory = \"Projects\"\n+++\nbody";
+        let meta = parse_front_matter(toml).unwrap().unwrap();
+        assert_eq!(meta.date.as_deref(), Some("2024-05-01T10:00:00Z"));
+        assert_eq!(meta.category.as_deref(), Some("Projects"));
+        assert!(meta.published);
+
+        assert_eq!(parse_front_matter("# No metadata\n---\n").unwrap(), None);
+        assert!(parse_front_matter("---\ntitle: never closed\n").is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let midnight = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(parse_date("2024-05-01"), Some(midnight));
+        assert_eq!(parse_date("2024-05-01T00:00:00Z"), Some(midnight));
+        assert_eq!(parse_date("2024-05-01 00:00"), Some(midnight));
+        assert_eq!(parse_date("May first"), None);
+    }
+
+    #[test]
+    fn loads_folder() {
+        let root = std::env::temp_dir().join(format!("jkcoxson-posts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("front")).unwrap();
+        std::fs::create_dir_all(root.join("toml")).unwrap();
+        std::fs::create_dir_all(root.join("database")).unwrap();
+        std::fs::write(
+            root.join("front/post.md"),
+            "---\ntitle: Front\ndate: 2024-01-01\n---\nbody",
+        )
+        .unwrap();
+        std::fs::write(root.join("toml/post.md"), "body").unwrap();
+        std::fs::write(
+            root.join("toml/post.toml"),
+            "title = \"Toml\"\ndate = 2024-02-01\ntags = [\"a\"]\nseries = \"Parts\"\nseries_part = 2",
+        )
+        .unwrap();
+        std::fs::write(root.join("database/post.md"), "no metadata").unwrap();
+
+        let posts = FilePosts::new(root.clone());
+        let loaded = posts.posts.read().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["front"].post_name, "Front");
+        assert_eq!(loaded["toml"].tags[0].tage_name, "a");
+        assert_eq!(loaded["toml"].file_path, root.join("toml/post.md"));
+        assert_eq!(
+            loaded["toml"].series,
+            Some(SeriesInfo {
+                name: "Parts".to_string(),
+                part: Some(2)
+            })
+        );
+        assert_eq!(loaded["front"].series, None);
+        drop(loaded);
+
+        std::fs::remove_dir_all(&root).unwrap();
+        posts.handle_change(&[root.join("front/post.md")]);
+        assert!(posts.posts.read().unwrap().is_empty());
+    }
+}
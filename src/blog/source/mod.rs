@@ -0,0 +1,215 @@
+// Jackson Coxson
+//
+// Where posts come from.
+// Posts can either be database rows pointing at a markdown file, or folders
+// in forge/blog with the metadata next to (or inside) the markdown.
+// Everything else in the blog only talks to `PostSources`, which merges them.
+// A source that fails is left out, so file posts keep working without the database.
+
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use super::structures::{Category, SeriesInfo, Tag};
+
+pub mod database;
+pub mod files;
+
+/// Everything the blog knows about a post, wherever it came from
+#[derive(Clone, Debug, Default)]
+pub struct PostRecord {
+    pub slug: String,
+    pub post_name: String,
+    pub file_path: PathBuf,
+    pub sneak_peak: Option<String>,
+    pub image_path: Option<String>,
+    pub author: Option<String>,
+    pub published: bool,
+    pub date_published: NaiveDateTime,
+    pub date_updated: Option<NaiveDateTime>,
+    pub category: Option<Category>,
+    pub tags: Vec<Tag>,
+    pub series: Option<SeriesInfo>,
+    /// Raw HTML in the post is kept as written instead of sanitized
+    pub trusted_html: bool,
+}
+
+impl PostRecord {
+    /// Published and not scheduled for later, dates are stored in UTC
+    pub fn is_live(&self, now: NaiveDateTime) -> bool {
+        self.published && self.date_published <= now
+    }
+
+    /// A published post from January 2024, for tests to fill in the rest of
+    #[cfg(test)]
+    pub fn fixture(slug: &str, day: u32) -> Self {
+        Self {
+            slug: slug.to_string(),
+            post_name: slug.to_string(),
+            published: true,
+            date_published: chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("database error: {0}")]
+    Sql(#[from] sqlx::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[async_trait]
+pub trait PostSource: Send + Sync {
+    /// Every post this source has, drafts included
+    async fn posts(&self) -> Result<Vec<PostRecord>, SourceError>;
+    /// Only the posts anyone can see right now
+    async fn live_posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        let now = chrono::Utc::now().naive_utc();
+        let mut posts = self.posts().await?;
+        posts.retain(|p| p.is_live(now));
+        Ok(posts)
+    }
+    /// A single post, or None if this source doesn't have it
+    async fn post(&self, slug: &str) -> Result<Option<PostRecord>, SourceError>;
+}
+
+/// A source that always has the same posts, for tests
+#[cfg(test)]
+pub struct FixedPosts(pub Vec<PostRecord>);
+
+#[cfg(test)]
+#[async_trait]
+impl PostSource for FixedPosts {
+    async fn posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        Ok(self.0.clone())
+    }
+
+    async fn post(&self, slug: &str) -> Result<Option<PostRecord>, SourceError> {
+        Ok(self.0.iter().find(|p| p.slug == slug).cloned())
+    }
+}
+
+/// All the configured sources, earlier ones win when two have the same slug
+#[derive(Clone, Default)]
+pub struct PostSources {
+    sources: Vec<Arc<dyn PostSource>>,
+}
+
+impl PostSources {
+    pub fn new(sources: Vec<Arc<dyn PostSource>>) -> Self {
+        Self { sources }
+    }
+
+    /// Every post from every source, newest first
+    pub async fn posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        let mut lists = Vec::with_capacity(self.sources.len());
+        for source in self.sources.iter() {
+            lists.push(source.posts().await);
+        }
+        merge(lists)
+    }
+
+    /// Every post that is published and past its publish date, newest first
+    pub async fn live_posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+        let mut lists = Vec::with_capacity(self.sources.len());
+        for source in self.sources.iter() {
+            lists.push(source.live_posts().await);
+        }
+        merge(lists)
+    }
+
+    /// The first source with `slug` wins. A failing source is only an error
+    /// if none of the others have the post, since it might have been there.
+    pub async fn post(&self, slug: &str) -> Result<Option<PostRecord>, SourceError> {
+        let mut failed = None;
+        for source in self.sources.iter() {
+            match source.post(slug).await {
+                Ok(Some(post)) => return Ok(Some(post)),
+                Ok(None) => {}
+                Err(e) => failed = Some(e),
+            }
+        }
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Sources that fail are left out, so the files are still listed while the
+/// database is down. It's only an error when every source failed.
+fn merge(lists: Vec<Result<Vec<PostRecord>, SourceError>>) -> Result<Vec<PostRecord>, SourceError> {
+    let mut posts: Vec<PostRecord> = Vec::new();
+    let mut answered = lists.is_empty();
+    let mut failed = None;
+    for list in lists {
+        match list {
+            Ok(list) => {
+                answered = true;
+                for post in list {
+                    if !posts.iter().any(|p| p.slug == post.slug) {
+                        posts.push(post);
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Leaving out a post source that failed: {e}");
+                failed = Some(e);
+            }
+        }
+    }
+    match failed {
+        Some(e) if !answered => Err(e),
+        _ => {
+            posts.sort_by_key(|p| std::cmp::Reverse(p.date_published));
+            Ok(posts)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Down;
+
+    #[async_trait]
+    impl PostSource for Down {
+        async fn posts(&self) -> Result<Vec<PostRecord>, SourceError> {
+            Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into())
+        }
+
+        async fn post(&self, _: &str) -> Result<Option<PostRecord>, SourceError> {
+            Err(std::io::Error::from(std::io::ErrorKind::NotConnected).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_sources_are_left_out() {
+        let files = Arc::new(FixedPosts(vec![
+            PostRecord::fixture("old", 1),
+            PostRecord::fixture("new", 2),
+        ]));
+        let sources = PostSources::new(vec![Arc::new(Down), files.clone()]);
+        let slugs = sources.live_posts().await.unwrap();
+        let slugs = slugs.iter().map(|p| p.slug.as_str()).collect::<Vec<_>>();
+        assert_eq!(slugs, ["new", "old"]);
+        assert!(sources.post("old").await.unwrap().is_some());
+        assert!(sources.post("missing").await.is_err());
+
+        let down = PostSources::new(vec![Arc::new(Down)]);
+        assert!(down.posts().await.is_err());
+        assert!(PostSources::new(vec![files])
+            .post("missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
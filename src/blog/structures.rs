@@ -8,19 +8,39 @@ pub mod raw {
     use sqlx::types::chrono::NaiveDateTime;
 
     #[derive(sqlx::FromRow)]
-    pub struct RawPostPreview {
-        pub post_name: String,
+    pub struct RawPost {
         pub slug: String,
+        pub post_name: String,
+        pub file_path: String,
         pub sneak_peak: Option<String>,
         pub image_path: Option<String>,
+        pub author: Option<String>,
         pub published: Option<bool>,
         pub date_published: NaiveDateTime,
         pub date_updated: Option<NaiveDateTime>,
         pub category: Option<i32>,
         pub category_name: Option<String>,
+        pub series: Option<String>,
+        pub series_part: Option<i32>,
+        pub trusted_html: Option<bool>,
     }
 }
 
+/// Which multi-part write-up a post belongs to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SeriesInfo {
+    pub name: String,
+    /// Parts without a number are ordered by date after the numbered ones
+    pub part: Option<i32>,
+}
+
+/// Every live part of a series, in reading order
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Series {
+    pub name: String,
+    pub posts: Vec<PostPreview>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PostPreview {
     pub post_name: String,
@@ -33,9 +53,21 @@ pub struct PostPreview {
     pub date_updated: Option<String>,
     pub category: Option<Category>,
     pub tags: Vec<Tag>,
+    pub series: Option<SeriesInfo>,
+}
+
+/// Where to go after finishing a post
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PostNavigation {
+    /// The post published just before this one
+    pub previous: Option<PostPreview>,
+    /// The post published just after this one
+    pub next: Option<PostPreview>,
+    pub related: Vec<PostPreview>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 pub struct Category {
     pub id: i32,
     pub category_name: String,
@@ -45,5 +77,95 @@ pub struct Category {
 #[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 pub struct Tag {
     pub id: i32,
+    #[cfg_attr(feature = "ssr", sqlx(rename = "tag_name"))]
     pub tage_name: String,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PostContent {
+    pub html: String,
+    pub post_name: String,
+    pub author: String,
+    pub date_published: NaiveDateTime,
+    pub date_updated: Option<NaiveDateTime>,
+    pub toc: Vec<TocEntry>,
+    pub word_count: usize,
+    pub reading_time: usize, // minutes
+    pub series: Option<SeriesInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TocEntry {
+    pub depth: u8,
+    pub text: String,
+    pub anchor: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PostMetadata {
+    pub title: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub canonical_url: String,
+    pub author: String,
+    pub date_published: NaiveDateTime,
+    pub date_updated: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SearchResult {
+    pub preview: PostPreview,
+    pub snippet: String, // HTML, matches are wrapped in <b>
+}
+
+/// A database post as the admin editor sees it
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AdminPost {
+    pub slug: String,
+    pub post_name: String,
+    pub sneak_peak: Option<String>,
+    pub image_path: Option<String>,
+    pub author: Option<String>,
+    pub published: bool,
+    pub date_published: NaiveDateTime,
+    pub category: Option<i32>,
+    pub tags: Vec<i32>, // tag ids
+    pub series: Option<String>,
+    pub series_part: Option<i32>,
+    /// Keep raw HTML as written, only for posts whose author is trusted
+    pub trusted_html: bool,
+    pub markdown: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdminPostSummary {
+    pub slug: String,
+    pub post_name: String,
+    pub published: bool,
+    pub date_published: NaiveDateTime,
+}
+
+/// An approved comment, with its approved replies
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Comment {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub author_name: String,
+    pub html: String,
+    pub created_at: NaiveDateTime,
+    pub replies: Vec<Comment>,
+}
+
+/// A comment waiting in the moderation queue
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct PendingComment {
+    pub id: i32,
+    pub slug: String,
+    pub parent_id: Option<i32>,
+    pub author_name: String,
+    pub email: Option<String>,
+    pub body: String, // markdown
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,146 @@
+// Jackson Coxson
+//
+// The contact form on the home page.
+// Messages are kept in contact_messages and then forwarded through whichever
+// notification sink is configured, see notify.rs.
+
+#[cfg(feature = "ssr")]
+use crate::context::Context;
+use crate::error_template::AppError;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+pub mod notify;
+
+pub const MAX_NAME: usize = 64;
+pub const MAX_EMAIL: usize = 255;
+pub const MAX_MESSAGE: usize = 5_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContactMessage {
+    pub name: String,
+    pub email: String,
+    pub message: String,
+}
+
+impl ContactMessage {
+    /// Trims everything and checks it's something worth reading
+    pub fn validate(name: &str, email: &str, message: &str) -> Result<Self, String> {
+        let name = name.trim();
+        let email = email.trim();
+        let message = message.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME {
+            return Err(format!(
+                "Names have to be between 1 and {MAX_NAME} characters"
+            ));
+        }
+        if !valid_email(email) {
+            return Err("That email doesn't look right".to_string());
+        }
+        if message.is_empty() || message.chars().count() > MAX_MESSAGE {
+            return Err(format!(
+                "Messages have to be between 1 and {MAX_MESSAGE} characters"
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            email: email.to_string(),
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Not RFC 5322, just enough to catch typos since the address is only used to reply
+fn valid_email(email: &str) -> bool {
+    if email.len() > MAX_EMAIL || email.chars().any(char::is_whitespace) {
+        return false;
+    }
+    match email.split_once('@') {
+        Some((user, domain)) => {
+            !user.is_empty()
+                && !domain.contains('@')
+                && domain
+                    .split_once('.')
+                    .is_some_and(|(a, b)| !a.is_empty() && !b.is_empty())
+        }
+        None => false,
+    }
+}
+
+/// Fetched after load instead of being in the HTML so scrapers have to try harder,
+/// the home page reads it with a LocalResource so it's never rendered on the server
+#[server(GetContactEmail, "/api", "getjson", "contact_email")]
+pub async fn get_contact_email() -> Result<Option<String>, AppError> {
+    let state = expect_context::<Context>();
+    Ok(state.contact_email.clone())
+}
+
+#[server(SendContactMessage, "/api")]
+pub async fn send_contact_message(
+    name: String,
+    email: String,
+    message: String,
+    website: Option<String>,
+) -> Result<(), AppError> {
+    let state = expect_context::<Context>();
+
+    if crate::honeypot::is_bot(&website) {
+        return Ok(());
+    }
+
+    let message =
+        ContactMessage::validate(&name, &email, &message).map_err(AppError::BadRequest)?;
+
+    let ip = crate::rate_limit::client_ip().await;
+    if let Err(wait) = state.contact_limiter.check(ip) {
+        return Err(AppError::rate_limited(wait));
+    }
+
+    state
+        .db
+        .add_contact_message(&message, ip.map(|i| i.to_string()))
+        .await?;
+
+    // It's saved, so a slow or broken sink shouldn't hold up the response
+    if let Some(notifier) = state.contact_notifier.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = notifier.notify(&message).await {
+                tracing::error!("Unable to send contact notification: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        let m = ContactMessage::validate("  Jackson ", "me@example.com", " hi ").unwrap();
+        assert_eq!(m.name, "Jackson");
+        assert_eq!(m.message, "hi");
+
+        assert!(ContactMessage::validate("", "me@example.com", "hi").is_err());
+        assert!(ContactMessage::validate("a", "me@example.com", "   ").is_err());
+        assert!(
+            ContactMessage::validate("a", "me@example.com", &"x".repeat(MAX_MESSAGE + 1)).is_err()
+        );
+        for email in [
+            "",
+            "me",
+            "me@",
+            "@example.com",
+            "me@example",
+            "me@@example.com",
+            "m e@example.com",
+        ] {
+            assert!(
+                ContactMessage::validate("a", email, "hi").is_err(),
+                "{email}"
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// Jackson Coxson
+//
+// Where new contact messages get forwarded to once they're stored.
+// Picked from the environment at startup, the first one configured wins:
+//   SMTP_HOST (+ SMTP_USER, SMTP_PASSWORD, CONTACT_FROM, CONTACT_TO)
+//   CONTACT_WEBHOOK
+//   CONTACT_FILE
+
+use std::{io::Write, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+
+use super::ContactMessage;
+
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    async fn notify(&self, message: &ContactMessage) -> Result<(), String>;
+}
+
+pub fn from_env() -> Option<Arc<dyn NotificationSink>> {
+    if let Some(smtp) = SmtpSink::from_env() {
+        return Some(Arc::new(smtp));
+    }
+    if let Ok(url) = std::env::var("CONTACT_WEBHOOK") {
+        if !url.is_empty() {
+            return Some(Arc::new(WebhookSink::new(url)));
+        }
+    }
+    if let Ok(path) = std::env::var("CONTACT_FILE") {
+        if !path.is_empty() {
+            return Some(Arc::new(FileSink::new(path)));
+        }
+    }
+    None
+}
+
+/// Emails the message, with the sender as the reply-to
+pub struct SmtpSink {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl SmtpSink {
+    pub fn from_env() -> Option<Self> {
+        let host = std::env::var("SMTP_HOST").ok().filter(|h| !h.is_empty())?;
+        let to: Mailbox = match std::env::var("CONTACT_TO").map(|t| t.parse()) {
+            Ok(Ok(t)) => t,
+            _ => {
+                tracing::warn!("SMTP_HOST is set but CONTACT_TO is missing or invalid");
+                return None;
+            }
+        };
+        let from = match std::env::var("CONTACT_FROM") {
+            Ok(f) => match f.parse() {
+                Ok(f) => f,
+                Err(e) => {
+                    tracing::warn!("CONTACT_FROM is invalid: {e:?}");
+                    return None;
+                }
+            },
+            Err(_) => to.clone(),
+        };
+
+        let mut mailer = match AsyncSmtpTransport::<Tokio1Executor>::relay(&host) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("Unable to set up SMTP relay {host}: {e:?}");
+                return None;
+            }
+        };
+        if let (Ok(user), Ok(password)) =
+            (std::env::var("SMTP_USER"), std::env::var("SMTP_PASSWORD"))
+        {
+            mailer = mailer.credentials(Credentials::new(user, password));
+        }
+        Some(Self {
+            mailer: mailer.build(),
+            from,
+            to,
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationSink for SmtpSink {
+    async fn notify(&self, message: &ContactMessage) -> Result<(), String> {
+        let mut builder = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(format!("Contact form: {}", message.name))
+            .header(ContentType::TEXT_PLAIN);
+        if let Ok(reply_to) = format!("{} <{}>", message.name, message.email).parse() {
+            builder = builder.reply_to(reply_to);
+        }
+        let email = builder
+            .body(format!(
+                "From: {} <{}>\n\n{}",
+                message.name, message.email, message.message
+            ))
+            .map_err(|e| e.to_string())?;
+        self.mailer
+            .send(email)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// POSTs the message as JSON
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationSink for WebhookSink {
+    async fn notify(&self, message: &ContactMessage) -> Result<(), String> {
+        self.client
+            .post(&self.url)
+            .json(message)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Appends each message as a line of JSON, handy for tests and local runs
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn append(&self, message: &ContactMessage) -> Result<(), String> {
+        let mut line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl NotificationSink for FileSink {
+    async fn notify(&self, message: &ContactMessage) -> Result<(), String> {
+        self.append(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_sink() {
+        let path = std::env::temp_dir().join(format!("contact-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = FileSink::new(&path);
+        let message = ContactMessage {
+            name: "Jackson".to_string(),
+            email: "me@example.com".to_string(),
+            message: "hi\nthere".to_string(),
+        };
+        sink.append(&message).unwrap();
+        sink.append(&message).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let back: ContactMessage = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(back.message, "hi\nthere");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
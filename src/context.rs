@@ -1,11 +1,41 @@
 // Jackson Coxson
 
-use sqlx::{MySql, Pool};
+use std::{path::PathBuf, sync::Arc};
 
-use crate::forge::buffer::ForgeRing;
+use crate::{
+    blog::{
+        admin::auth::AdminAuth, cache::PostCache, preview::PreviewKey, search_index::SearchIndex,
+        source::PostSources,
+    },
+    contact::notify::NotificationSink,
+    db::Repository,
+    forge::buffer::ForgeRing,
+    rate_limit::RateLimiter,
+    security::SecurityHeaders,
+};
 
 #[derive(Clone)]
 pub struct Context {
     pub forge: ForgeRing,
-    pub sql_pool: Pool<MySql>,
+    /// MySQL or SQLite, depending on DATABASE_URL
+    pub db: Arc<dyn Repository>,
+    pub post_cache: PostCache,
+    pub search_index: SearchIndex,
+    pub posts: PostSources,
+    /// None when PREVIEW_SECRET isn't set, which disables previews
+    pub preview_key: Option<PreviewKey>,
+    /// None when ADMIN_PASSWORD isn't set, which disables /admin/blog
+    pub admin: Option<AdminAuth>,
+    /// Where forge shortcodes in posts are looked up
+    pub forge_dir: PathBuf,
+    /// forge/blog, where posts and their images are kept
+    pub blog_dir: PathBuf,
+    pub comment_limiter: RateLimiter,
+    pub contact_limiter: RateLimiter,
+    /// None when no sink is configured, messages are still stored
+    pub contact_notifier: Option<Arc<dyn NotificationSink>>,
+    /// CONTACT_EMAIL, shown on the home page
+    pub contact_email: Option<String>,
+    /// The CSP and friends, pages need it to send their nonce
+    pub security: SecurityHeaders,
 }
@@ -0,0 +1,276 @@
+// Jackson Coxson
+//
+// The server shouldn't refuse to start, or fall over, because the database is.
+// `LazyRepository` connects in the background and keeps retrying with backoff,
+// until then every query fails as unavailable (see `is_unavailable`) so pages can show a 503.
+// Once connected the pool reconnects by itself, connection errors only mark it unhealthy.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use super::{CommentRow, DbResult, NewComment, PoolStats, Repository};
+use crate::{
+    blog::{
+        source::PostRecord,
+        structures::{AdminPost, Category, PendingComment, Tag},
+    },
+    contact::ContactMessage,
+};
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(60);
+const UNAVAILABLE: &str = "the database is unavailable";
+
+pub struct LazyRepository {
+    inner: OnceLock<Arc<dyn Repository>>,
+    healthy: AtomicBool,
+}
+
+impl LazyRepository {
+    /// Starts connecting to `url` in the background, migrating first if asked.
+    /// Without a url the database stays unavailable.
+    pub fn connect(url: Option<String>, migrate: bool) -> Arc<Self> {
+        let lazy = Arc::new(Self {
+            inner: OnceLock::new(),
+            healthy: AtomicBool::new(false),
+        });
+        let Some(url) = url else {
+            tracing::warn!("DATABASE_URL not set, running without a database");
+            return lazy;
+        };
+
+        let connecting = lazy.clone();
+        tokio::task::spawn(async move {
+            let mut delay = FIRST_RETRY;
+            let db = loop {
+                match super::connect(&url).await {
+                    Ok(db) => break db,
+                    // A url we can't use won't get better by waiting
+                    Err(e @ sqlx::Error::Configuration(_)) => {
+                        tracing::error!("Unable to connect to the database: {e}");
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Unable to connect to the database, retrying in {delay:?}: {e}"
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(MAX_RETRY);
+                    }
+                }
+            };
+            if migrate {
+                if let Err(e) = db.migrate().await {
+                    tracing::error!("Unable to run migrations: {e}");
+                }
+            }
+            tracing::info!("Connected to the database");
+            let _ = connecting.inner.set(db);
+            connecting.healthy.store(true, Ordering::Relaxed);
+        });
+        lazy
+    }
+
+    /// Connected, and the last query didn't lose the connection
+    pub fn is_healthy(&self) -> bool {
+        self.inner.get().is_some() && self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn get(&self) -> DbResult<&Arc<dyn Repository>> {
+        self.inner.get().ok_or_else(unavailable)
+    }
+
+    /// Keeps the health flag up to date, and turns lost connections into `unavailable()`
+    fn track<T>(&self, result: DbResult<T>) -> DbResult<T> {
+        match result {
+            Err(e) if is_connection_error(&e) => {
+                if self.healthy.swap(false, Ordering::Relaxed) {
+                    tracing::error!("Lost the database connection: {e}");
+                }
+                Err(unavailable())
+            }
+            result => {
+                self.healthy.store(true, Ordering::Relaxed);
+                result
+            }
+        }
+    }
+}
+
+fn unavailable() -> sqlx::Error {
+    sqlx::Error::Io(io::Error::new(io::ErrorKind::NotConnected, UNAVAILABLE))
+}
+
+/// Whether a query failed because the database can't be reached right now
+pub fn is_unavailable(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Io(e) if e.kind() == io::ErrorKind::NotConnected)
+}
+
+fn is_connection_error(e: &sqlx::Error) -> bool {
+    matches!(
+        e,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
+// Each query runs in a debug span named after it
+#[async_trait]
+impl Repository for LazyRepository {
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        self.get()?.migrate().await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn ping(&self) -> DbResult<()> {
+        self.track(self.get()?.ping().await)
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.inner.get()?.pool_stats()
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.track(self.get()?.posts().await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn live_posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.track(self.get()?.live_posts().await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn post(&self, slug: &str) -> DbResult<Option<PostRecord>> {
+        self.track(self.get()?.post(slug).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, post))]
+    async fn save_post(&self, post: &AdminPost, file_path: &str, is_new: bool) -> DbResult<()> {
+        self.track(self.get()?.save_post(post, file_path, is_new).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn set_published(&self, slug: &str, published: bool) -> DbResult<()> {
+        self.track(self.get()?.set_published(slug, published).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn categories(&self) -> DbResult<Vec<Category>> {
+        self.track(self.get()?.categories().await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn create_category(&self, name: &str) -> DbResult<()> {
+        self.track(self.get()?.create_category(name).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn delete_category(&self, id: i32) -> DbResult<()> {
+        self.track(self.get()?.delete_category(id).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn tags(&self) -> DbResult<Vec<Tag>> {
+        self.track(self.get()?.tags().await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn create_tag(&self, name: &str) -> DbResult<()> {
+        self.track(self.get()?.create_tag(name).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn delete_tag(&self, id: i32) -> DbResult<()> {
+        self.track(self.get()?.delete_tag(id).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn approved_comments(&self, slug: &str) -> DbResult<Vec<CommentRow>> {
+        self.track(self.get()?.approved_comments(slug).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn is_approved_comment(&self, id: i32, slug: &str) -> DbResult<bool> {
+        self.track(self.get()?.is_approved_comment(id, slug).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, comment))]
+    async fn add_comment(&self, comment: NewComment<'_>) -> DbResult<()> {
+        self.track(self.get()?.add_comment(comment).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn pending_comments(&self) -> DbResult<Vec<PendingComment>> {
+        self.track(self.get()?.pending_comments().await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn approve_comment(&self, id: i32) -> DbResult<()> {
+        self.track(self.get()?.approve_comment(id).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn comment_replies(&self, id: i32) -> DbResult<Vec<i32>> {
+        self.track(self.get()?.comment_replies(id).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn delete_comment(&self, id: i32) -> DbResult<()> {
+        self.track(self.get()?.delete_comment(id).await)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, message))]
+    async fn add_contact_message(
+        &self,
+        message: &ContactMessage,
+        ip: Option<String>,
+    ) -> DbResult<()> {
+        self.track(self.get()?.add_contact_message(message, ip).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blog::source::SourceError, error_template::AppError};
+
+    /// What a server function would hand the error template
+    fn app_error(e: sqlx::Error) -> AppError {
+        AppError::from(SourceError::from(e))
+    }
+
+    #[tokio::test]
+    async fn unavailable_until_connected() {
+        let missing = LazyRepository::connect(None, false);
+        let err = missing.posts().await.unwrap_err();
+        assert!(matches!(app_error(err), AppError::Unavailable));
+        assert!(!missing.is_healthy());
+
+        let db = LazyRepository::connect(Some("sqlite::memory:".to_string()), true);
+        for _ in 0..50 {
+            if db.is_healthy() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        db.ping().await.unwrap();
+        assert!(db.posts().await.unwrap().is_empty());
+
+        let err = sqlx::Error::RowNotFound;
+        assert!(!is_connection_error(&err));
+        assert!(matches!(app_error(err), AppError::InternalServerError));
+    }
+}
@@ -0,0 +1,296 @@
+// Jackson Coxson
+//
+// Everything the site keeps in a database goes through `Repository`.
+// MySQL (or MariaDB) is what runs in production, SQLite is there so the site
+// and the tests can run without a database server. `connect` picks one from
+// the DATABASE_URL scheme, mysql://... or sqlite://...
+//
+// Each backend's schema lives in migrations/<backend> and is embedded in the binary.
+// `jkcoxson --migrate` applies anything new before serving, and `jkcoxson seed`
+// migrates and then fills the database with a little content for local development.
+// The server itself connects through `lazy::LazyRepository`, so it can start
+// (and keep serving the forge) while the database is down.
+
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::{
+    blog::{
+        source::PostRecord,
+        structures::{raw::RawPost, AdminPost, Category, PendingComment, SeriesInfo, Tag},
+    },
+    contact::ContactMessage,
+};
+
+pub mod lazy;
+pub mod mysql;
+pub mod sqlite;
+
+pub type DbResult<T> = Result<T, sqlx::Error>;
+
+/// How long a query waits for a connection before giving up,
+/// short enough that a page can say the database is down instead of hanging
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Both backends select posts the same way, only the time functions differ
+const SELECT_POSTS: &str = r#"
+SELECT
+    posts.slug,
+    posts.post_name,
+    posts.file_path,
+    posts.sneak_peak,
+    posts.image_path,
+    posts.author,
+    posts.published,
+    posts.date_published,
+    posts.date_updated,
+    posts.category,
+    categories.category_name,
+    posts.series,
+    posts.series_part,
+    posts.trusted_html
+FROM posts
+LEFT JOIN categories ON posts.category = categories.id
+"#;
+
+const SELECT_TAGS: &str = r#"
+SELECT
+    post_tags.slug,
+    tags.id,
+    tags.tag_name
+FROM post_tags
+JOIN tags ON post_tags.tag_id = tags.id
+"#;
+
+/// A snapshot of a connection pool, for /metrics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+/// An approved comment before its markdown is rendered
+#[derive(sqlx::FromRow)]
+pub struct CommentRow {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub author_name: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A comment as it was submitted, it waits for moderation once stored
+pub struct NewComment<'a> {
+    pub slug: &'a str,
+    pub parent_id: Option<i32>,
+    pub author_name: &'a str,
+    pub email: Option<&'a str>,
+    pub body: &'a str,
+    pub ip: Option<String>,
+}
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    /// Applies every migration the database hasn't seen yet
+    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError>;
+    /// A round trip to the database, for health checks
+    async fn ping(&self) -> DbResult<()>;
+    /// None until there's a pool to ask
+    fn pool_stats(&self) -> Option<PoolStats>;
+
+    /// Every post, drafts included
+    async fn posts(&self) -> DbResult<Vec<PostRecord>>;
+    /// Published posts whose date has passed, newest first
+    async fn live_posts(&self) -> DbResult<Vec<PostRecord>>;
+    async fn post(&self, slug: &str) -> DbResult<Option<PostRecord>>;
+    /// Creates or updates a post and replaces its tags.
+    /// Updating a live post bumps its date_updated.
+    async fn save_post(&self, post: &AdminPost, file_path: &str, is_new: bool) -> DbResult<()>;
+    async fn set_published(&self, slug: &str, published: bool) -> DbResult<()>;
+
+    async fn categories(&self) -> DbResult<Vec<Category>>;
+    async fn create_category(&self, name: &str) -> DbResult<()>;
+    /// Posts in the category are left without one
+    async fn delete_category(&self, id: i32) -> DbResult<()>;
+    async fn tags(&self) -> DbResult<Vec<Tag>>;
+    async fn create_tag(&self, name: &str) -> DbResult<()>;
+    async fn delete_tag(&self, id: i32) -> DbResult<()>;
+
+    /// A post's approved comments, oldest first
+    async fn approved_comments(&self, slug: &str) -> DbResult<Vec<CommentRow>>;
+    /// Whether `id` is an approved comment on `slug`, so it can be replied to
+    async fn is_approved_comment(&self, id: i32, slug: &str) -> DbResult<bool>;
+    async fn add_comment(&self, comment: NewComment<'_>) -> DbResult<()>;
+    /// The moderation queue, oldest first
+    async fn pending_comments(&self) -> DbResult<Vec<PendingComment>>;
+    async fn approve_comment(&self, id: i32) -> DbResult<()>;
+    /// The ids of the direct replies to a comment
+    async fn comment_replies(&self, id: i32) -> DbResult<Vec<i32>>;
+    async fn delete_comment(&self, id: i32) -> DbResult<()>;
+
+    async fn add_contact_message(
+        &self,
+        message: &ContactMessage,
+        ip: Option<String>,
+    ) -> DbResult<()>;
+}
+
+/// Connects to the database in `url`, choosing the backend from its scheme
+pub async fn connect(url: &str) -> DbResult<Arc<dyn Repository>> {
+    match url.split_once(':').map(|(scheme, _)| scheme) {
+        Some("mysql" | "mariadb") => Ok(Arc::new(mysql::MySqlRepository::connect(url).await?)),
+        Some("sqlite") => Ok(Arc::new(sqlite::SqliteRepository::connect(url).await?)),
+        _ => Err(sqlx::Error::Configuration(
+            format!("DATABASE_URL should start with mysql:// or sqlite://, not {url:?}").into(),
+        )),
+    }
+}
+
+/// Pairs each post with its tags from a SELECT_TAGS query
+fn with_tags(posts: Vec<RawPost>, tag_rows: Vec<(String, i32, String)>) -> Vec<PostRecord> {
+    let mut tags: HashMap<String, Vec<Tag>> = HashMap::new();
+    for (slug, id, tag_name) in tag_rows {
+        tags.entry(slug).or_default().push(Tag {
+            id,
+            tage_name: tag_name,
+        });
+    }
+    posts
+        .into_iter()
+        .map(|p| {
+            let tags = tags.remove(&p.slug).unwrap_or_default();
+            record(p, tags)
+        })
+        .collect()
+}
+
+fn record(p: RawPost, tags: Vec<Tag>) -> PostRecord {
+    PostRecord {
+        slug: p.slug,
+        post_name: p.post_name,
+        file_path: p.file_path.into(),
+        sneak_peak: p.sneak_peak,
+        image_path: p.image_path,
+        author: p.author,
+        published: p.published.unwrap_or(false),
+        date_published: p.date_published,
+        date_updated: p.date_updated,
+        category: match (p.category, p.category_name) {
+            (Some(id), Some(category_name)) => Some(Category { id, category_name }),
+            _ => None,
+        },
+        tags,
+        series: p.series.map(|name| SeriesInfo {
+            name,
+            part: p.series_part,
+        }),
+        trusted_html: p.trusted_html.unwrap_or(false),
+    }
+}
+
+const SEED_CATEGORY: &str = "Projects";
+const SEED_TAGS: [&str; 2] = ["rust", "meta"];
+const SEED_SLUG: &str = "hello-world";
+const SEED_POST: &str = r#"# Hello, world
+
+This post was added by `jkcoxson seed` so there's something to look at.
+Edit it from [/admin/blog](/admin/blog), or delete it.
+
+> [!TIP]
+> Posts can also live entirely in `forge/blog`, no database needed.
+
+```rust
+fn main() {
+    println!("Hello, world!");
+}
+```
+"#;
+
+/// Adds a category, some tags, a post and a comment.
+/// Running it again leaves everything that's already there alone.
+pub async fn seed(db: &dyn Repository, blog_dir: &Path) -> Result<(), String> {
+    if db
+        .post(SEED_SLUG)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Ok(());
+    }
+
+    let file_path = blog_dir.join(SEED_SLUG).join("post.md");
+    if !file_path.exists() {
+        std::fs::create_dir_all(blog_dir.join(SEED_SLUG)).map_err(|e| e.to_string())?;
+        std::fs::write(&file_path, SEED_POST).map_err(|e| e.to_string())?;
+    }
+
+    if !db
+        .categories()
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .any(|c| c.category_name == SEED_CATEGORY)
+    {
+        db.create_category(SEED_CATEGORY)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let category = db
+        .categories()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|c| c.category_name == SEED_CATEGORY)
+        .map(|c| c.id);
+
+    let existing = db.tags().await.map_err(|e| e.to_string())?;
+    for tag in SEED_TAGS {
+        if !existing.iter().any(|t| t.tage_name == tag) {
+            db.create_tag(tag).await.map_err(|e| e.to_string())?;
+        }
+    }
+    let tags = db
+        .tags()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|t| SEED_TAGS.contains(&t.tage_name.as_str()))
+        .map(|t| t.id)
+        .collect();
+
+    let post = AdminPost {
+        slug: SEED_SLUG.to_string(),
+        post_name: "Hello, world".to_string(),
+        sneak_peak: Some("The first post on a fresh database".to_string()),
+        published: true,
+        date_published: chrono::Utc::now().naive_utc(),
+        category,
+        tags,
+        ..Default::default()
+    };
+    db.save_post(&post, &file_path.to_string_lossy(), true)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    db.add_comment(NewComment {
+        slug: SEED_SLUG,
+        parent_id: None,
+        author_name: "Jackson",
+        email: None,
+        body: "First!",
+        ip: None,
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    for pending in db.pending_comments().await.map_err(|e| e.to_string())? {
+        if pending.slug == SEED_SLUG {
+            db.approve_comment(pending.id)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,347 @@
+// Jackson Coxson
+//
+// The production backend, dates are stored in UTC and compared with UTC_TIMESTAMP()
+
+use async_trait::async_trait;
+use sqlx::{migrate::Migrator, mysql::MySqlPoolOptions, MySql, Pool};
+
+use super::{
+    record, with_tags, CommentRow, DbResult, NewComment, PoolStats, Repository, ACQUIRE_TIMEOUT,
+    SELECT_POSTS, SELECT_TAGS,
+};
+use crate::{
+    blog::{
+        source::PostRecord,
+        structures::{raw::RawPost, AdminPost, Category, PendingComment, Tag},
+    },
+    contact::ContactMessage,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/mysql");
+
+pub struct MySqlRepository {
+    pool: Pool<MySql>,
+}
+
+impl MySqlRepository {
+    pub async fn connect(url: &str) -> DbResult<Self> {
+        Ok(Self {
+            pool: MySqlPoolOptions::new()
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect(url)
+                .await?,
+        })
+    }
+
+    async fn posts_where(&self, filter: &str) -> DbResult<Vec<PostRecord>> {
+        let posts = sqlx::query_as::<_, RawPost>(&format!("{SELECT_POSTS} {filter}"))
+            .fetch_all(&self.pool)
+            .await?;
+        let tags = sqlx::query_as::<_, (String, i32, String)>(SELECT_TAGS)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(with_tags(posts, tags))
+    }
+}
+
+#[async_trait]
+impl Repository for MySqlRepository {
+    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self) -> DbResult<()> {
+        sqlx::query("SELECT 1;").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+            max: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.posts_where("ORDER BY posts.date_published DESC").await
+    }
+
+    async fn live_posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.posts_where(
+            "WHERE posts.published = 1 AND posts.date_published <= UTC_TIMESTAMP() ORDER BY posts.date_published DESC",
+        )
+        .await
+    }
+
+    async fn post(&self, slug: &str) -> DbResult<Option<PostRecord>> {
+        let post =
+            match sqlx::query_as::<_, RawPost>(&format!("{SELECT_POSTS} WHERE posts.slug = ?"))
+                .bind(slug)
+                .fetch_optional(&self.pool)
+                .await?
+            {
+                Some(p) => p,
+                None => return Ok(None),
+            };
+        let tags = sqlx::query_as::<_, (String, i32, String)>(&format!(
+            "{SELECT_TAGS} WHERE post_tags.slug = ?"
+        ))
+        .bind(slug)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(_, id, tag_name)| Tag {
+            id,
+            tage_name: tag_name,
+        })
+        .collect();
+        Ok(Some(record(post, tags)))
+    }
+
+    async fn save_post(&self, post: &AdminPost, file_path: &str, is_new: bool) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        if is_new {
+            sqlx::query(
+                r#"
+INSERT INTO posts
+    (slug, post_name, file_path, author, sneak_peak, image_path, published, date_published, category, series, series_part, trusted_html)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
+"#,
+            )
+            .bind(&post.slug)
+            .bind(&post.post_name)
+            .bind(file_path)
+            .bind(&post.author)
+            .bind(&post.sneak_peak)
+            .bind(&post.image_path)
+            .bind(post.published)
+            .bind(post.date_published)
+            .bind(post.category)
+            .bind(&post.series)
+            .bind(post.series_part)
+            .bind(post.trusted_html)
+            .execute(&mut *tx)
+            .await?;
+        } else {
+            // MySQL assigns left to right, so date_updated sees the old published state
+            sqlx::query(
+                r#"
+UPDATE posts SET
+    date_updated = IF(published = 1 AND date_published <= UTC_TIMESTAMP(), UTC_TIMESTAMP(), date_updated),
+    post_name = ?,
+    author = ?,
+    sneak_peak = ?,
+    image_path = ?,
+    published = ?,
+    date_published = ?,
+    category = ?,
+    series = ?,
+    series_part = ?,
+    trusted_html = ?
+WHERE slug = ?;
+"#,
+            )
+            .bind(&post.post_name)
+            .bind(&post.author)
+            .bind(&post.sneak_peak)
+            .bind(&post.image_path)
+            .bind(post.published)
+            .bind(post.date_published)
+            .bind(post.category)
+            .bind(&post.series)
+            .bind(post.series_part)
+            .bind(post.trusted_html)
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM post_tags WHERE slug = ?;")
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        for tag in post.tags.iter() {
+            sqlx::query("INSERT INTO post_tags (slug, tag_id) VALUES (?, ?);")
+                .bind(&post.slug)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn set_published(&self, slug: &str, published: bool) -> DbResult<()> {
+        sqlx::query("UPDATE posts SET published = ? WHERE slug = ?;")
+            .bind(published)
+            .bind(slug)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn categories(&self) -> DbResult<Vec<Category>> {
+        sqlx::query_as::<_, Category>(
+            "SELECT id, category_name FROM categories ORDER BY category_name;",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_category(&self, name: &str) -> DbResult<()> {
+        sqlx::query("INSERT INTO categories (category_name) VALUES (?);")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_category(&self, id: i32) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "UPDATE posts SET category = NULL WHERE category = ?;",
+            "DELETE FROM categories WHERE id = ?;",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn tags(&self) -> DbResult<Vec<Tag>> {
+        sqlx::query_as::<_, Tag>("SELECT id, tag_name FROM tags ORDER BY tag_name;")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_tag(&self, name: &str) -> DbResult<()> {
+        sqlx::query("INSERT INTO tags (tag_name) VALUES (?);")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_tag(&self, id: i32) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "DELETE FROM post_tags WHERE tag_id = ?;",
+            "DELETE FROM tags WHERE id = ?;",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn approved_comments(&self, slug: &str) -> DbResult<Vec<CommentRow>> {
+        sqlx::query_as::<_, CommentRow>(
+            r#"
+SELECT
+    id,
+    parent_id,
+    author_name,
+    body,
+    created_at
+FROM comments
+WHERE slug = ? AND approved = 1
+ORDER BY created_at, id;
+"#,
+        )
+        .bind(slug)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn is_approved_comment(&self, id: i32, slug: &str) -> DbResult<bool> {
+        Ok(sqlx::query_scalar::<_, i32>(
+            "SELECT id FROM comments WHERE id = ? AND slug = ? AND approved = 1;",
+        )
+        .bind(id)
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn add_comment(&self, comment: NewComment<'_>) -> DbResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO comments
+    (slug, parent_id, author_name, email, body, ip, approved, created_at)
+VALUES (?, ?, ?, ?, ?, ?, 0, UTC_TIMESTAMP());
+"#,
+        )
+        .bind(comment.slug)
+        .bind(comment.parent_id)
+        .bind(comment.author_name)
+        .bind(comment.email)
+        .bind(comment.body)
+        .bind(comment.ip)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_comments(&self) -> DbResult<Vec<PendingComment>> {
+        sqlx::query_as::<_, PendingComment>(
+            r#"
+SELECT
+    id,
+    slug,
+    parent_id,
+    author_name,
+    email,
+    body,
+    created_at
+FROM comments
+WHERE approved = 0
+ORDER BY created_at, id;
+"#,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn approve_comment(&self, id: i32) -> DbResult<()> {
+        sqlx::query("UPDATE comments SET approved = 1 WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn comment_replies(&self, id: i32) -> DbResult<Vec<i32>> {
+        sqlx::query_scalar::<_, i32>("SELECT id FROM comments WHERE parent_id = ?;")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_comment(&self, id: i32) -> DbResult<()> {
+        sqlx::query("DELETE FROM comments WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_contact_message(
+        &self,
+        message: &ContactMessage,
+        ip: Option<String>,
+    ) -> DbResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO contact_messages
+    (name, email, message, ip, created_at)
+VALUES (?, ?, ?, ?, UTC_TIMESTAMP());
+"#,
+        )
+        .bind(&message.name)
+        .bind(&message.email)
+        .bind(&message.message)
+        .bind(ip)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,517 @@
+// Jackson Coxson
+//
+// For running without a database server, and for the tests.
+// SQLite has no UTC_TIMESTAMP(), so the current time is bound from here instead.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use std::str::FromStr;
+
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+
+use super::{
+    record, with_tags, CommentRow, DbResult, NewComment, PoolStats, Repository, ACQUIRE_TIMEOUT,
+    SELECT_POSTS, SELECT_TAGS,
+};
+use crate::{
+    blog::{
+        source::PostRecord,
+        structures::{raw::RawPost, AdminPost, Category, PendingComment, Tag},
+    },
+    contact::ContactMessage,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+
+pub struct SqliteRepository {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteRepository {
+    /// Creates the file if it's missing. In-memory databases only exist
+    /// for a single connection, so those get a pool of one.
+    pub async fn connect(url: &str) -> DbResult<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+        Ok(Self {
+            pool: SqlitePoolOptions::new()
+                .max_connections(if in_memory { 1 } else { 8 })
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect_with(options)
+                .await?,
+        })
+    }
+
+    /// `now` is bound to the filter's placeholder, if it has one
+    async fn posts_where(
+        &self,
+        filter: &str,
+        now: Option<NaiveDateTime>,
+    ) -> DbResult<Vec<PostRecord>> {
+        let query = format!("{SELECT_POSTS} {filter}");
+        let mut posts = sqlx::query_as::<_, RawPost>(&query);
+        if let Some(now) = now {
+            posts = posts.bind(now);
+        }
+        let posts = posts.fetch_all(&self.pool).await?;
+        let tags = sqlx::query_as::<_, (String, i32, String)>(SELECT_TAGS)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(with_tags(posts, tags))
+    }
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn ping(&self) -> DbResult<()> {
+        sqlx::query("SELECT 1;").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+            max: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.posts_where("ORDER BY posts.date_published DESC", None)
+            .await
+    }
+
+    async fn live_posts(&self) -> DbResult<Vec<PostRecord>> {
+        self.posts_where(
+            "WHERE posts.published = 1 AND posts.date_published <= ? ORDER BY posts.date_published DESC",
+            Some(now()),
+        )
+        .await
+    }
+
+    async fn post(&self, slug: &str) -> DbResult<Option<PostRecord>> {
+        let post =
+            match sqlx::query_as::<_, RawPost>(&format!("{SELECT_POSTS} WHERE posts.slug = ?"))
+                .bind(slug)
+                .fetch_optional(&self.pool)
+                .await?
+            {
+                Some(p) => p,
+                None => return Ok(None),
+            };
+        let tags = sqlx::query_as::<_, (String, i32, String)>(&format!(
+            "{SELECT_TAGS} WHERE post_tags.slug = ?"
+        ))
+        .bind(slug)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(_, id, tag_name)| Tag {
+            id,
+            tage_name: tag_name,
+        })
+        .collect();
+        Ok(Some(record(post, tags)))
+    }
+
+    async fn save_post(&self, post: &AdminPost, file_path: &str, is_new: bool) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        if is_new {
+            sqlx::query(
+                r#"
+INSERT INTO posts
+    (slug, post_name, file_path, author, sneak_peak, image_path, published, date_published, category, series, series_part, trusted_html)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
+"#,
+            )
+            .bind(&post.slug)
+            .bind(&post.post_name)
+            .bind(file_path)
+            .bind(&post.author)
+            .bind(&post.sneak_peak)
+            .bind(&post.image_path)
+            .bind(post.published)
+            .bind(post.date_published)
+            .bind(post.category)
+            .bind(&post.series)
+            .bind(post.series_part)
+            .bind(post.trusted_html)
+            .execute(&mut *tx)
+            .await?;
+        } else {
+            // Every assignment sees the old row, so date_updated checks the old published state
+            sqlx::query(
+                r#"
+UPDATE posts SET
+    date_updated = CASE WHEN published = 1 AND date_published <= ?1 THEN ?1 ELSE date_updated END,
+    post_name = ?2,
+    author = ?3,
+    sneak_peak = ?4,
+    image_path = ?5,
+    published = ?6,
+    date_published = ?7,
+    category = ?8,
+    series = ?9,
+    series_part = ?10,
+    trusted_html = ?11
+WHERE slug = ?12;
+"#,
+            )
+            .bind(now())
+            .bind(&post.post_name)
+            .bind(&post.author)
+            .bind(&post.sneak_peak)
+            .bind(&post.image_path)
+            .bind(post.published)
+            .bind(post.date_published)
+            .bind(post.category)
+            .bind(&post.series)
+            .bind(post.series_part)
+            .bind(post.trusted_html)
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM post_tags WHERE slug = ?;")
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        for tag in post.tags.iter() {
+            sqlx::query("INSERT INTO post_tags (slug, tag_id) VALUES (?, ?);")
+                .bind(&post.slug)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn set_published(&self, slug: &str, published: bool) -> DbResult<()> {
+        sqlx::query("UPDATE posts SET published = ? WHERE slug = ?;")
+            .bind(published)
+            .bind(slug)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn categories(&self) -> DbResult<Vec<Category>> {
+        sqlx::query_as::<_, Category>(
+            "SELECT id, category_name FROM categories ORDER BY category_name;",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn create_category(&self, name: &str) -> DbResult<()> {
+        sqlx::query("INSERT INTO categories (category_name) VALUES (?);")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_category(&self, id: i32) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "UPDATE posts SET category = NULL WHERE category = ?;",
+            "DELETE FROM categories WHERE id = ?;",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn tags(&self) -> DbResult<Vec<Tag>> {
+        sqlx::query_as::<_, Tag>("SELECT id, tag_name FROM tags ORDER BY tag_name;")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create_tag(&self, name: &str) -> DbResult<()> {
+        sqlx::query("INSERT INTO tags (tag_name) VALUES (?);")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_tag(&self, id: i32) -> DbResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "DELETE FROM post_tags WHERE tag_id = ?;",
+            "DELETE FROM tags WHERE id = ?;",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn approved_comments(&self, slug: &str) -> DbResult<Vec<CommentRow>> {
+        sqlx::query_as::<_, CommentRow>(
+            r#"
+SELECT
+    id,
+    parent_id,
+    author_name,
+    body,
+    created_at
+FROM comments
+WHERE slug = ? AND approved = 1
+ORDER BY created_at, id;
+"#,
+        )
+        .bind(slug)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn is_approved_comment(&self, id: i32, slug: &str) -> DbResult<bool> {
+        Ok(sqlx::query_scalar::<_, i32>(
+            "SELECT id FROM comments WHERE id = ? AND slug = ? AND approved = 1;",
+        )
+        .bind(id)
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some())
+    }
+
+    async fn add_comment(&self, comment: NewComment<'_>) -> DbResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO comments
+    (slug, parent_id, author_name, email, body, ip, approved, created_at)
+VALUES (?, ?, ?, ?, ?, ?, 0, ?);
+"#,
+        )
+        .bind(comment.slug)
+        .bind(comment.parent_id)
+        .bind(comment.author_name)
+        .bind(comment.email)
+        .bind(comment.body)
+        .bind(comment.ip)
+        .bind(now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_comments(&self) -> DbResult<Vec<PendingComment>> {
+        sqlx::query_as::<_, PendingComment>(
+            r#"
+SELECT
+    id,
+    slug,
+    parent_id,
+    author_name,
+    email,
+    body,
+    created_at
+FROM comments
+WHERE approved = 0
+ORDER BY created_at, id;
+"#,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn approve_comment(&self, id: i32) -> DbResult<()> {
+        sqlx::query("UPDATE comments SET approved = 1 WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn comment_replies(&self, id: i32) -> DbResult<Vec<i32>> {
+        sqlx::query_scalar::<_, i32>("SELECT id FROM comments WHERE parent_id = ?;")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn delete_comment(&self, id: i32) -> DbResult<()> {
+        sqlx::query("DELETE FROM comments WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_contact_message(
+        &self,
+        message: &ContactMessage,
+        ip: Option<String>,
+    ) -> DbResult<()> {
+        sqlx::query(
+            r#"
+INSERT INTO contact_messages
+    (name, email, message, ip, created_at)
+VALUES (?, ?, ?, ?, ?);
+"#,
+        )
+        .bind(&message.name)
+        .bind(&message.email)
+        .bind(&message.message)
+        .bind(ip)
+        .bind(now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+fn now() -> NaiveDateTime {
+    chrono::Utc::now().naive_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::seed;
+
+    async fn db() -> SqliteRepository {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.migrate().await.unwrap();
+        db
+    }
+
+    fn post(slug: &str, days_ago: i64) -> AdminPost {
+        AdminPost {
+            slug: slug.to_string(),
+            post_name: slug.to_string(),
+            published: true,
+            date_published: now() - chrono::Duration::days(days_ago),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn posts() {
+        let db = db().await;
+        db.create_category("Projects").await.unwrap();
+        db.create_tag("rust").await.unwrap();
+        db.create_tag("ios").await.unwrap();
+        let category = db.categories().await.unwrap()[0].id;
+        let tags = db.tags().await.unwrap();
+
+        let mut first = post("first", 2);
+        first.category = Some(category);
+        first.tags = tags.iter().map(|t| t.id).collect();
+        first.series = Some("Parts".to_string());
+        first.series_part = Some(1);
+        db.save_post(&first, "first.md", true).await.unwrap();
+        db.save_post(&post("second", 1), "second.md", true)
+            .await
+            .unwrap();
+        db.save_post(&post("scheduled", -1), "scheduled.md", true)
+            .await
+            .unwrap();
+        let mut draft = post("draft", 1);
+        draft.published = false;
+        db.save_post(&draft, "draft.md", true).await.unwrap();
+
+        let live = db.live_posts().await.unwrap();
+        let slugs = live.iter().map(|p| p.slug.as_str()).collect::<Vec<_>>();
+        assert_eq!(slugs, ["second", "first"]);
+        assert_eq!(db.posts().await.unwrap().len(), 4);
+
+        let first = db.post("first").await.unwrap().unwrap();
+        assert_eq!(first.category.unwrap().category_name, "Projects");
+        assert_eq!(first.tags.len(), 2);
+        assert_eq!(first.series.unwrap().part, Some(1));
+        assert_eq!(first.file_path, std::path::PathBuf::from("first.md"));
+        assert!(first.date_updated.is_none());
+        assert!(db.post("nope").await.unwrap().is_none());
+
+        // Editing a live post marks it updated and replaces its tags
+        let mut edited = post("first", 2);
+        edited.tags = vec![tags[0].id];
+        db.save_post(&edited, "first.md", false).await.unwrap();
+        let first = db.post("first").await.unwrap().unwrap();
+        assert!(first.date_updated.is_some());
+        assert_eq!(first.tags.len(), 1);
+        assert!(first.category.is_none());
+
+        // Drafts aren't
+        db.save_post(&draft, "draft.md", false).await.unwrap();
+        assert!(db
+            .post("draft")
+            .await
+            .unwrap()
+            .unwrap()
+            .date_updated
+            .is_none());
+
+        db.set_published("draft", true).await.unwrap();
+        assert_eq!(db.live_posts().await.unwrap().len(), 3);
+
+        // Deleting tags and categories leaves the posts
+        db.delete_tag(tags[0].id).await.unwrap();
+        assert!(db.post("first").await.unwrap().unwrap().tags.is_empty());
+        db.delete_category(category).await.unwrap();
+        assert!(db.categories().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn comments() {
+        let db = db().await;
+        let comment = |parent_id, body| NewComment {
+            slug: "post",
+            parent_id,
+            author_name: "Someone",
+            email: None,
+            body,
+            ip: Some("127.0.0.1".to_string()),
+        };
+        db.add_comment(comment(None, "Hi")).await.unwrap();
+        assert!(db.approved_comments("post").await.unwrap().is_empty());
+        let pending = db.pending_comments().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let id = pending[0].id;
+        assert!(!db.is_approved_comment(id, "post").await.unwrap());
+
+        db.approve_comment(id).await.unwrap();
+        assert!(db.is_approved_comment(id, "post").await.unwrap());
+        assert!(!db.is_approved_comment(id, "other").await.unwrap());
+
+        db.add_comment(comment(Some(id), "Reply")).await.unwrap();
+        let reply = db.pending_comments().await.unwrap()[0].id;
+        db.approve_comment(reply).await.unwrap();
+        let approved = db.approved_comments("post").await.unwrap();
+        assert_eq!(approved.len(), 2);
+        assert_eq!(approved[1].parent_id, Some(id));
+        assert_eq!(db.comment_replies(id).await.unwrap(), [reply]);
+
+        db.delete_comment(reply).await.unwrap();
+        assert_eq!(db.approved_comments("post").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn contact_and_seed() {
+        let db = db().await;
+        let message = ContactMessage::validate("Someone", "a@b.c", "Hello").unwrap();
+        db.add_contact_message(&message, None).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jkcoxson-seed-{}", std::process::id()));
+        seed(&db, &dir).await.unwrap();
+        seed(&db, &dir).await.unwrap();
+        let posts = db.live_posts().await.unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].tags.len(), 2);
+        assert!(posts[0].file_path.is_file());
+        assert_eq!(db.approved_comments(&posts[0].slug).await.unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
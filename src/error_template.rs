@@ -1,11 +1,26 @@
 use http::status::StatusCode;
 use leptos::prelude::*;
+use leptos::server_fn::codec::JsonEncoding;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Clone, Debug, Error)]
+/// What server functions fail with, and what error pages are made from.
+/// Anything the visitor can't do anything about is logged on the server
+/// and sent as an InternalServerError, so details don't leak out.
+#[derive(Clone, Debug, Error, PartialEq, Serialize, Deserialize)]
 pub enum AppError {
     #[error("Not Found")]
     NotFound,
+    #[error("Log in to do that")]
+    Unauthorized,
+    #[error("You don't have access to that")]
+    Forbidden,
+    #[error("Slow down a little, try again in {}", wait(*retry_after))]
+    RateLimited { retry_after: u64 },
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("The blog is taking a break, try again in a minute")]
+    Unavailable,
     #[error("Internal Server Error")]
     InternalServerError,
 }
@@ -14,9 +29,89 @@ impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
             AppError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// Logs what went wrong, the client only hears that something did
+    #[cfg(feature = "ssr")]
+    pub fn internal(e: impl std::fmt::Display) -> Self {
+        tracing::error!("{e}");
+        AppError::InternalServerError
+    }
+
+    /// Over a limit until `wait` has passed. Called from a server function, its own
+    /// response gets the 429 and Retry-After too, not just a page showing the error.
+    #[cfg(feature = "ssr")]
+    pub fn rate_limited(wait: std::time::Duration) -> Self {
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+            response.set_status(StatusCode::TOO_MANY_REQUESTS);
+            response.insert_header(http::header::RETRY_AFTER, retry_after.into());
+        }
+        AppError::RateLimited { retry_after }
+    }
+}
+
+/// "3 minutes" or "40 seconds", rounded up so trying again then works
+fn wait(seconds: u64) -> String {
+    match seconds {
+        1 => "a second".to_string(),
+        s if s < 60 => format!("{s} seconds"),
+        60 => "a minute".to_string(),
+        s => format!("{} minutes", s.div_ceil(60)),
+    }
+}
+
+impl FromServerFnError for AppError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        match value {
+            e @ (ServerFnErrorErr::Args(_) | ServerFnErrorErr::MissingArg(_)) => {
+                AppError::BadRequest(e.to_string())
+            }
+            // The server couldn't be reached at all
+            ServerFnErrorErr::Request(_) => AppError::Unavailable,
+            e => {
+                tracing::error!("{e}");
+                AppError::InternalServerError
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        if crate::db::lazy::is_unavailable(&e) {
+            AppError::Unavailable
+        } else {
+            AppError::internal(e)
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<crate::blog::source::SourceError> for AppError {
+    fn from(e: crate::blog::source::SourceError) -> Self {
+        match e {
+            crate::blog::source::SourceError::Sql(e) => e.into(),
+            e => AppError::internal(e),
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::internal(e)
+    }
 }
 
 // A basic function to display errors served by the error boundaries.
@@ -41,7 +136,7 @@ pub fn ErrorTemplate(
         .into_iter()
         .filter_map(|(_k, v)| v.downcast_ref::<AppError>().cloned())
         .collect();
-    println!("Errors: {errors:#?}");
+    tracing::info!("Rendering an error page: {errors:?}");
 
     // Only the response code for the first error is actually sent from the server
     // this may be customized by the specific application
@@ -49,8 +144,11 @@ pub fn ErrorTemplate(
     {
         use leptos_axum::ResponseOptions;
         let response = use_context::<ResponseOptions>();
-        if let Some(response) = response {
-            response.set_status(errors[0].status_code());
+        if let (Some(response), Some(error)) = (response, errors.first()) {
+            response.set_status(error.status_code());
+            if let AppError::RateLimited { retry_after } = error {
+                response.insert_header(http::header::RETRY_AFTER, (*retry_after).into());
+            }
         }
     }
 
@@ -76,3 +174,42 @@ pub fn ErrorTemplate(
         </div>
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let limited = AppError::RateLimited { retry_after: 150 };
+        assert_eq!(limited.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            limited.to_string(),
+            "Slow down a little, try again in 3 minutes"
+        );
+        assert_eq!(
+            AppError::RateLimited { retry_after: 20 }.to_string(),
+            "Slow down a little, try again in 20 seconds"
+        );
+        assert_eq!(wait(60), "a minute");
+        assert_eq!(wait(61), "2 minutes");
+        assert_eq!(wait(119), "2 minutes");
+        #[cfg(feature = "ssr")]
+        assert_eq!(
+            AppError::rate_limited(std::time::Duration::from_millis(19_200)),
+            AppError::RateLimited { retry_after: 20 }
+        );
+        assert_eq!(
+            AppError::BadRequest("Tags need a name".to_string()).to_string(),
+            "Tags need a name"
+        );
+        assert_eq!(
+            AppError::from_server_fn_error(ServerFnErrorErr::MissingArg("slug".to_string())),
+            AppError::BadRequest("missing argument slug".to_string())
+        );
+
+        // What the client gets back from a failed server function
+        let json = serde_json::to_string(&limited).unwrap();
+        assert_eq!(serde_json::from_str::<AppError>(&json).unwrap(), limited);
+    }
+}
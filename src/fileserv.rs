@@ -31,13 +31,27 @@ pub async fn file_and_error_handler(
     let path: Vec<&str> = static_parts.uri.path().split('/').collect();
     if path.len() > 2 && path[1] == "cdn" {
         let context = context.clone();
-        if let Ok(f) = context
-            .forge
-            .get()
-            .lock()
-            .await
-            .get(path[2..].to_vec(), None)
-        {
+        // Drafts' folders and every post's source 404 like files that aren't there
+        let token = crate::blog::drafts::token(&parts.headers, parts.uri.query());
+        let allowed = crate::blog::drafts::can_serve(
+            &context.posts,
+            context.preview_key.as_ref(),
+            &path[2..],
+            token.as_deref(),
+        )
+        .await;
+        let found = if allowed {
+            context
+                .forge
+                .get()
+                .lock()
+                .await
+                .get(path[2..].to_vec(), None)
+                .ok()
+        } else {
+            None
+        };
+        if let Some(f) = found {
             match f {
                 crate::forge::ForgeReturnType::File(f) => {
                     // Serve the file
@@ -33,12 +33,23 @@ impl ForgeRing {
         self.ring[pointer as usize].clone()
     }
 
+    /// Whether every forge's last load succeeded
+    pub async fn is_loaded(&self) -> bool {
+        for forge in self.ring.iter() {
+            if !forge.lock().await.is_loaded() {
+                return false;
+            }
+        }
+        true
+    }
+
     /// Spawns a thread to watch the forge folder for changes
     /// When an update is detected, update each forge appropriately
-    pub fn watch(&self) {
+    /// and then let `on_change` know which paths were touched
+    pub fn watch(&self, on_change: impl Fn(&notify::Event) + Send + 'static) {
         let forges = self.ring.clone();
         tokio::task::spawn(async move {
-            println!("Watching the forge folder");
+            tracing::info!("Watching the forge folder");
             let mut watcher =
                 notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
                     match res {
@@ -51,12 +62,13 @@ impl ForgeRing {
                                 forges.iter().for_each(|forge| {
                                     // TODO: make this actually reload the tree
                                     if let Err(e) = forge.blocking_lock().reload() {
-                                        eprintln!("Failed to reload Forge: {e:?}");
+                                        tracing::error!("Failed to reload Forge: {e:?}");
                                     }
                                 });
+                                on_change(&event);
                             }
                         }
-                        Err(e) => println!("watch error: {:?}", e),
+                        Err(e) => tracing::error!("watch error: {:?}", e),
                     }
                 })
                 .expect("Watcher failed to create");
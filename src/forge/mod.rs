@@ -16,11 +16,13 @@
 // |  | -- v0.1.1/           // Symantic versioning will be used
 // |  |  | -- folder3.txt    // The file will be served at /folder3.txt?v=0.1.1 or /folder3.txt since it's the latest
 
-use std::{collections::HashMap, io::Read, path::PathBuf};
+use std::{collections::HashMap, io::Read, path::PathBuf, sync::atomic::Ordering, time::Instant};
 
 use hashlink::LinkedHashMap;
 use tree::Node;
 
+use crate::metrics::METRICS;
+
 pub mod buffer;
 mod config;
 mod converters;
@@ -32,6 +34,8 @@ pub struct Forge {
     cache: LinkedHashMap<String, (Vec<u8>, String)>,
     cache_limit: usize,
     path: PathBuf,
+    /// False while the last reload failed and the old tree is still being served
+    loaded: bool,
 }
 
 #[derive(Clone)]
@@ -96,24 +100,45 @@ impl Forge {
         let head = Self::load(path.clone(), 0)?;
         let node: Node = head.into();
         let node = node.take_first_child().unwrap();
-        println!("Loaded tree");
+        tracing::info!("Loaded tree");
         Ok(Forge {
             inner: node,
             cache: LinkedHashMap::with_capacity(cache_limit),
             cache_limit,
             path,
+            loaded: true,
         })
     }
 
     pub fn reload(&mut self) -> Result<(), std::io::Error> {
-        println!("Reloading tree");
-        self.inner = Node::from(Self::load(self.path.clone(), 0)?)
-            .take_first_child()
-            .unwrap();
-        self.cache.clear();
+        tracing::info!("Reloading tree");
+        let start = Instant::now();
+        METRICS.forge_reloads.fetch_add(1, Ordering::Relaxed);
+        let loaded = Self::load(self.path.clone(), 0);
+        METRICS.forge_reload_duration.observe(start.elapsed());
+        self.loaded = loaded.is_ok();
+        if !self.loaded {
+            METRICS
+                .forge_reload_failures
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner = Node::from(loaded?).take_first_child().unwrap();
+        self.clear_cache();
         Ok(())
     }
 
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    fn clear_cache(&mut self) {
+        let bytes: usize = self.cache.values().map(|(buf, _)| buf.len()).sum();
+        METRICS
+            .forge_cache_bytes
+            .fetch_sub(bytes as u64, Ordering::Relaxed);
+        self.cache.clear();
+    }
+
     pub fn get(
         &mut self,
         request: Vec<&str>,
@@ -121,9 +146,13 @@ impl Forge {
     ) -> Result<ForgeReturnType, std::io::Error> {
         // Search the cache for a answer
         let cache_search = request.join("/");
+        let _span = tracing::debug_span!("forge_get", path = %cache_search).entered();
         if let Some(res) = self.cache.to_front(&cache_search) {
+            METRICS.forge_cache_hits.fetch_add(1, Ordering::Relaxed);
+            tracing::trace!("cache hit");
             return Ok(ForgeReturnType::File(res.to_owned()));
         }
+        METRICS.forge_cache_misses.fetch_add(1, Ordering::Relaxed);
         if let Some(r) = self.inner.traverse(request) {
             // Did we get a file or dir?
             match r {
@@ -143,8 +172,15 @@ impl Forge {
 
                     // Place in the cache
                     if self.cache.len() == self.cache_limit {
-                        self.cache.pop_back();
+                        if let Some((_, (evicted, _))) = self.cache.pop_back() {
+                            METRICS
+                                .forge_cache_bytes
+                                .fetch_sub(evicted.len() as u64, Ordering::Relaxed);
+                        }
                     }
+                    METRICS
+                        .forge_cache_bytes
+                        .fetch_add(buf.len() as u64, Ordering::Relaxed);
                     self.cache
                         .insert(cache_search, (buf.clone(), entry.content_type.clone()));
 
@@ -162,6 +198,7 @@ impl Forge {
 
     /// Gets a view of a folder, dirs and files inside said node
     pub fn view(&self, request: Vec<&str>) -> Result<(Vec<String>, Vec<String>), std::io::Error> {
+        let _span = tracing::debug_span!("forge_view", path = request.join("/")).entered();
         if let Some(r) = self.inner.traverse(request) {
             match r {
                 tree::NodeTraverseReturn::Dir(node) => {
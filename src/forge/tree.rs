@@ -22,7 +22,7 @@ impl Node {
     pub fn print(&self) {
         // Start with the children
         for (name, node) in &self.children {
-            println!(
+            tracing::debug!(
                 "{}{}{}",
                 "-".repeat(self.depth),
                 name,
@@ -31,7 +31,7 @@ impl Node {
             node.print();
         }
         for (name, file) in self.files.iter() {
-            println!(
+            tracing::debug!(
                 "{}{}{}",
                 "-".repeat(self.depth),
                 name,
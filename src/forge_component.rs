@@ -3,7 +3,7 @@
 use leptos::prelude::*;
 
 use leptos_meta::Title;
-use leptos_router::hooks::use_location;
+use leptos_router::hooks::{use_location, use_query_map};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -24,15 +24,16 @@ pub fn ForgeComponent() -> impl IntoView {
             <h1>Forge</h1>
 
             {
+                let query = use_query_map();
                 let resource = Resource::new(
-                    move || use_location().pathname.get(),
-                    |route| async move {
+                    move || (use_location().pathname.get(), query.get().get("preview")),
+                    |(route, preview)| async move {
                         let split_route = route
                             .split('/')
                             .map(|r| r.to_string())
                             .collect::<Vec<String>>();
-                        println!("loading data from API");
-                        print_tree(split_route).await
+                        tracing::debug!("loading data from API");
+                        print_tree(split_route, preview).await
                     },
                 );
                 view! {
@@ -79,19 +80,9 @@ pub fn ForgeComponent() -> impl IntoView {
                                         }
                                     }
                                     Err(e) => {
-                                        match e {
-                                            ServerFnError::Request(_) => {
-                                                let mut outside_errors = Errors::default();
-                                                outside_errors.insert_with_default_key(AppError::NotFound);
-                                                view! { <ErrorTemplate outside_errors /> }.into_any()
-                                            }
-                                            _ => {
-                                                let mut outside_errors = Errors::default();
-                                                outside_errors
-                                                    .insert_with_default_key(AppError::InternalServerError);
-                                                view! { <ErrorTemplate outside_errors /> }.into_any()
-                                            }
-                                        }
+                                        let mut outside_errors = Errors::default();
+                                        outside_errors.insert_with_default_key(e);
+                                        view! { <ErrorTemplate outside_errors /> }.into_any()
                                     }
                                 }
                             }
@@ -120,24 +111,33 @@ pub enum PrintReturn {
     Dir((Vec<String>, Vec<String>)),
 }
 
+/// `preview` is a draft's preview token, its folder isn't listed without it
 #[server(PrintTree, "/api")]
-pub async fn print_tree(request: Vec<String>) -> Result<PrintReturn, ServerFnError> {
-    let state = expect_context::<Context>();
-    let state = state.forge.get();
+pub async fn print_tree(
+    request: Vec<String>,
+    preview: Option<String>,
+) -> Result<PrintReturn, AppError> {
+    let context = expect_context::<Context>();
+    let state = context.forge.get();
 
     let borrowed_request: Vec<&str> = request
         .iter()
         .filter(|s| !s.is_empty())
         .map(|r| r.as_str())
         .collect();
-    let data = match state.lock().await.view(borrowed_request[1..].to_vec()) {
+    let path = &borrowed_request[1..];
+    let key = context.preview_key.as_ref();
+    if !crate::blog::drafts::can_serve(&context.posts, key, path, preview.as_deref()).await {
+        return Err(AppError::NotFound);
+    }
+    let mut data = match state.lock().await.view(path.to_vec()) {
         Ok(data) => data,
         Err(e) => match e.kind() {
             std::io::ErrorKind::NotFound => {
-                return Err(ServerFnError::Request("File not found".to_string()));
+                return Err(AppError::NotFound);
             }
             std::io::ErrorKind::InvalidData => {
-                println!("Redirecting from: {borrowed_request:?}");
+                tracing::debug!("Redirecting from: {borrowed_request:?}");
                 leptos_axum::redirect(format!("/cdn/{}", borrowed_request[1..].join("/")).as_str());
                 return Ok(PrintReturn::File);
             }
@@ -145,6 +145,8 @@ pub async fn print_tree(request: Vec<String>) -> Result<PrintReturn, ServerFnErr
         },
     };
 
+    let (dirs, files) = &mut data;
+    crate::blog::drafts::hide_entries(&context.posts, key, path, dirs, files).await;
     Ok(PrintReturn::Dir(data))
 }
 
@@ -0,0 +1,48 @@
+// Jackson Coxson
+//
+// Endpoints for whatever is watching the server.
+// /healthz only says the process is up, /readyz says whether it can serve everything,
+// and /metrics has the counters from metrics.rs in the Prometheus text format.
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{context::Context, metrics::METRICS};
+
+pub async fn healthz() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!({ "status": "ok" })))
+}
+
+/// 200 when the database answers and the forge is loaded, 503 with what's wrong otherwise
+pub async fn readyz(context: Context) -> impl IntoResponse {
+    let database = match context.db.ping().await {
+        Ok(()) => "up".to_string(),
+        Err(e) => e.to_string(),
+    };
+    let forge = if context.forge.is_loaded().await {
+        "loaded"
+    } else {
+        "reload failed"
+    };
+    let ready = database == "up" && forge == "loaded";
+    (
+        if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(json!({
+            "status": if ready { "ready" } else { "degraded" },
+            "database": database,
+            "forge": forge,
+        })),
+    )
+}
+
+pub async fn metrics(context: Context) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [("content-type", "text/plain; version=0.0.4")],
+        METRICS.render(context.db.pool_stats()),
+    )
+}
@@ -0,0 +1,43 @@
+// Jackson Coxson
+//
+// Spam protection for the comment and contact forms.
+// Both carry a field people never see but form-filling bots do, and a
+// submission with it filled in is dropped while the bot is told it worked,
+// so it doesn't try anything smarter.
+
+use leptos::prelude::*;
+
+/// What the hidden field is called, server functions take it as `website`
+pub const FIELD: &str = "website";
+
+/// Goes inside the form, next to the real fields
+#[component]
+pub fn Honeypot() -> impl IntoView {
+    view! {
+        <input
+            type="text"
+            name=FIELD
+            tabindex="-1"
+            autocomplete="off"
+            aria-hidden="true"
+            class="hidden"
+        />
+    }
+}
+
+/// Whether the hidden field was filled in. Answer with `Ok(())` if so.
+pub fn is_bot(website: &Option<String>) -> bool {
+    website.as_ref().is_some_and(|w| !w.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bots() {
+        assert!(is_bot(&Some("https://spam.example".to_string())));
+        assert!(!is_bot(&Some(String::new())));
+        assert!(!is_bot(&None));
+    }
+}
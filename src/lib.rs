@@ -6,14 +6,32 @@ pub mod error_template;
 pub mod forge_component;
 
 pub mod blog;
+pub mod contact;
 #[cfg(feature = "ssr")]
 pub mod context;
 #[cfg(feature = "ssr")]
+pub mod db;
+#[cfg(feature = "ssr")]
 pub mod fileserv;
 #[cfg(feature = "ssr")]
 pub mod forge;
+#[cfg(feature = "ssr")]
+pub mod health;
+pub mod honeypot;
 pub mod idevice_tools;
 pub mod jitstreamer;
+#[cfg(feature = "ssr")]
+pub mod metrics;
+#[cfg(feature = "ssr")]
+pub mod rate_limit;
+#[cfg(feature = "ssr")]
+pub mod security;
+#[cfg(feature = "ssr")]
+pub mod server;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
+#[cfg(feature = "ssr")]
+pub mod tls;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
@@ -23,13 +41,6 @@ pub fn hydrate() {
     leptos::prelude::hydrate_body(App);
 }
 
-#[cfg(feature = "hydrate")]
-#[wasm_bindgen::prelude::wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen::prelude::wasm_bindgen(js_namespace = hljs)]
-    fn highlightAll();
-}
-
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn reload() {
@@ -38,10 +49,6 @@ pub fn reload() {
     }
 }
 
-#[cfg(not(feature = "hydrate"))]
-#[allow(non_snake_case)]
-fn highlightAll() {}
-
 #[cfg(not(feature = "hydrate"))]
 #[allow(non_snake_case)]
 fn reload() {}
@@ -5,7 +5,6 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
-    use sqlx::mysql::MySqlPoolOptions;
     use std::sync::Arc;
     use tokio::sync::Mutex;
 
@@ -14,8 +13,52 @@ async fn main() {
     use jkcoxson::{app::*, context::Context};
     use leptos::prelude::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
+    use tower_http::{
+        request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+        trace::{DefaultOnResponse, TraceLayer},
+    };
 
     dotenvy::dotenv().ok();
+    jkcoxson::telemetry::init();
+
+    // `jkcoxson preview <slug> [days]` prints a link for sharing a draft
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("preview") {
+        use jkcoxson::blog::preview::{PreviewKey, DEFAULT_LIFETIME};
+        let (Some(slug), Some(key)) = (args.get(2), PreviewKey::from_env()) else {
+            eprintln!("usage: PREVIEW_SECRET=... jkcoxson preview <slug> [days]");
+            std::process::exit(1);
+        };
+        let lifetime = args
+            .get(3)
+            .and_then(|d| d.parse().ok())
+            .map(chrono::Duration::days)
+            .unwrap_or(DEFAULT_LIFETIME);
+        println!(
+            "{}/blog/{slug}?preview={}",
+            jkcoxson::blog::SITE_URL,
+            key.sign_for(slug, lifetime)
+        );
+        return;
+    }
+
+    // `jkcoxson seed` sets up a database for local development, see db.rs
+    let blog_dir = std::env::current_dir()
+        .expect("Unable to get the current path")
+        .join("forge")
+        .join("blog");
+    if args.get(1).map(String::as_str) == Some("seed") {
+        let db =
+            jkcoxson::db::connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL not set"))
+                .await
+                .expect("Unable to connect to the database!");
+        db.migrate().await.expect("Unable to run migrations");
+        jkcoxson::db::seed(db.as_ref(), &blog_dir)
+            .await
+            .expect("Unable to seed the database");
+        tracing::info!("Seeded the database, the post is at /blog/hello-world");
+        return;
+    }
     let conf = get_configuration(Some("Cargo.toml")).unwrap();
     let mut leptos_options = conf.leptos_options;
     // Files build to <site-root>/pkg/, but they're served via the forge under /cdn/...
@@ -36,19 +79,69 @@ async fn main() {
         )));
     }
     let forge_ring = jkcoxson::forge::buffer::ForgeRing::new(forges);
-    forge_ring.watch();
+    // Rendered blog posts are dropped as soon as their file changes,
+    // and the search index is rebuilt on the next search
+    let post_cache = jkcoxson::blog::cache::PostCache::new(64);
+    let search_index = jkcoxson::blog::search_index::SearchIndex::default();
+    // Posts that live only in forge/blog are indexed at startup and whenever the folder changes
+    let file_posts = jkcoxson::blog::source::files::FilePosts::new(blog_dir.clone());
+    let watched_cache = post_cache.clone();
+    let watched_index = search_index.clone();
+    let watched_posts = file_posts.clone();
+    forge_ring.watch(move |event| {
+        watched_cache.invalidate(&event.paths);
+        watched_index.mark_dirty();
+        watched_posts.handle_change(&event.paths);
+    });
 
-    // Connect to MySQL database
-    let pool = MySqlPoolOptions::new()
-        .connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL not set"))
-        .await
-        .expect("Unable to connect to MySQL database!");
+    // Migrations only run when asked, with --migrate or RUN_MIGRATIONS=true
+    let run_migrations = args.iter().any(|a| a == "--migrate")
+        || std::env::var("RUN_MIGRATIONS").is_ok_and(|v| v == "true" || v == "1");
+    // Connect to the MySQL or SQLite database in the background, retrying until it's up.
+    // Until then the blog shows a 503 and everything else works as usual.
+    let db: Arc<dyn jkcoxson::db::Repository> = jkcoxson::db::lazy::LazyRepository::connect(
+        std::env::var("DATABASE_URL").ok(),
+        run_migrations,
+    );
+
+    // Files win over database rows with the same slug
+    let posts = jkcoxson::blog::source::PostSources::new(vec![
+        Arc::new(file_posts),
+        Arc::new(jkcoxson::blog::source::database::DatabasePosts::new(
+            db.clone(),
+        )),
+    ]);
 
     let context = Context {
         forge: forge_ring.clone(),
-        sql_pool: pool,
+        db,
+        post_cache,
+        search_index,
+        posts,
+        preview_key: jkcoxson::blog::preview::PreviewKey::from_env(),
+        admin: jkcoxson::blog::admin::auth::AdminAuth::from_env(),
+        forge_dir: path.clone(),
+        blog_dir,
+        // A few comments per person every 10 minutes
+        comment_limiter: jkcoxson::rate_limit::RateLimiter::new(
+            3,
+            std::time::Duration::from_secs(10 * 60),
+        ),
+        // Nobody needs to send more than a couple messages an hour
+        contact_limiter: jkcoxson::rate_limit::RateLimiter::new(
+            2,
+            std::time::Duration::from_secs(60 * 60),
+        ),
+        contact_notifier: jkcoxson::contact::notify::from_env(),
+        contact_email: std::env::var("CONTACT_EMAIL")
+            .ok()
+            .filter(|e| !e.is_empty()),
+        security: jkcoxson::security::SecurityHeaders::from_env(),
     };
     let app_context = context.clone();
+    let ready_context = context.clone();
+    let metrics_context = context.clone();
+    let security = context.security.clone();
 
     // build our application with a route
     let shell_options = leptos_options.clone();
@@ -60,14 +153,59 @@ async fn main() {
             move || shell(shell_options.clone()),
         )
         .route("/favicon.ico", axum::routing::get(get_favicon))
+        .route("/healthz", axum::routing::get(jkcoxson::health::healthz))
+        .route(
+            "/readyz",
+            axum::routing::get(move || jkcoxson::health::readyz(ready_context.clone())),
+        )
+        .route(
+            "/metrics",
+            axum::routing::get(move || jkcoxson::health::metrics(metrics_context.clone())),
+        )
         .fallback(|state, req| file_and_error_handler(state, req, context))
+        // CSP, HSTS and the rest, see security.rs
+        .layer(axum::middleware::from_fn_with_state(
+            security,
+            jkcoxson::security::headers,
+        ))
+        // Per-IP request and download limits, see rate_limit.rs
+        .layer(axum::middleware::from_fn_with_state(
+            jkcoxson::rate_limit::RateLimits::from_env(),
+            jkcoxson::rate_limit::limit,
+        ))
+        // Counts and times every request by route group, see metrics.rs
+        .layer(axum::middleware::from_fn(jkcoxson::metrics::track))
+        // Tags each request with an id and logs it, see telemetry.rs
+        .layer(
+            tower::ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(jkcoxson::telemetry::request_span)
+                        .on_response(DefaultOnResponse::new().level(tracing::Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
         .with_state(leptos_options);
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    println!("listening on http://{}", &addr);
-    axum::serve(listener, app.into_make_service())
+    // Inherited from systemd when socket activated, see server.rs
+    let listener = jkcoxson::server::listener(0, addr)
         .await
-        .unwrap();
+        .expect("Unable to bind the listener");
+    tracing::info!("listening on http://{}", listener.local_addr().unwrap());
+    // With TLS_CERT and TLS_KEY set, HTTP only redirects to HTTPS, see tls.rs
+    match jkcoxson::tls::TlsConfig::from_env() {
+        Some(tls) => {
+            let https = jkcoxson::server::listener(1, tls.addr)
+                .await
+                .expect("Unable to bind the HTTPS listener");
+            tracing::info!("listening on https://{}", https.local_addr().unwrap());
+            jkcoxson::tls::serve(listener, https, tls, app)
+                .await
+                .expect("Unable to serve HTTPS");
+        }
+        None => jkcoxson::server::serve(listener, app).await.unwrap(),
+    }
 }
 
 pub async fn get_favicon() -> impl axum::response::IntoResponse {
@@ -0,0 +1,300 @@
+// Jackson Coxson
+//
+// Counters for /metrics, kept in atomics and written out in the Prometheus text format.
+// Requests are counted by a middleware layer, the forge counts its own cache and reloads,
+// and the database pool is asked for its numbers when /metrics is scraped.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::db::PoolStats;
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds in seconds, shared by every histogram
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Requests are grouped by what served them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RouteGroup {
+    Cdn,
+    Api,
+    Ssr,
+}
+
+impl RouteGroup {
+    const ALL: [RouteGroup; 3] = [RouteGroup::Cdn, RouteGroup::Api, RouteGroup::Ssr];
+
+    /// None for the monitoring endpoints themselves
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path.split('/').nth(1).unwrap_or_default() {
+            "healthz" | "readyz" | "metrics" => None,
+            "cdn" => Some(RouteGroup::Cdn),
+            "api" => Some(RouteGroup::Api),
+            _ => Some(RouteGroup::Ssr),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RouteGroup::Cdn => "cdn",
+            RouteGroup::Api => "api",
+            RouteGroup::Ssr => "ssr",
+        }
+    }
+}
+
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|b| seconds <= *b) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Buckets are stored on their own and written out cumulatively
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = format!("le=\"{bound}\"");
+            let _ = writeln!(out, "{name}_bucket{} {cumulative}", braces(&[labels, &le]));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(
+            out,
+            "{name}_bucket{} {count}",
+            braces(&[labels, "le=\"+Inf\""])
+        );
+        let _ = writeln!(out, "{name}_sum{} {sum}", braces(&[labels]));
+        let _ = writeln!(out, "{name}_count{} {count}", braces(&[labels]));
+    }
+}
+
+/// `{a,b}` from the labels that aren't empty, or nothing at all
+fn braces(labels: &[&str]) -> String {
+    let labels = labels
+        .iter()
+        .filter(|l| !l.is_empty())
+        .copied()
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+pub struct Metrics {
+    /// Indexed by RouteGroup, then by status class (1xx to 5xx)
+    requests: [[AtomicU64; 5]; 3],
+    latency: [Histogram; 3],
+    pub forge_cache_hits: AtomicU64,
+    pub forge_cache_misses: AtomicU64,
+    /// Bytes held across every forge's cache
+    pub forge_cache_bytes: AtomicU64,
+    pub forge_reloads: AtomicU64,
+    pub forge_reload_failures: AtomicU64,
+    pub forge_reload_duration: Histogram,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            requests: [const { [const { AtomicU64::new(0) }; 5] }; 3],
+            latency: [const { Histogram::new() }; 3],
+            forge_cache_hits: AtomicU64::new(0),
+            forge_cache_misses: AtomicU64::new(0),
+            forge_cache_bytes: AtomicU64::new(0),
+            forge_reloads: AtomicU64::new(0),
+            forge_reload_failures: AtomicU64::new(0),
+            forge_reload_duration: Histogram::new(),
+        }
+    }
+
+    pub fn record_request(&self, group: RouteGroup, status: u16, duration: Duration) {
+        let class = (status / 100).clamp(1, 5) as usize - 1;
+        self.requests[group as usize][class].fetch_add(1, Ordering::Relaxed);
+        self.latency[group as usize].observe(duration);
+    }
+
+    /// Everything, plus the pool's numbers if there's a database
+    pub fn render(&self, pool: Option<PoolStats>) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP http_requests_total Requests served, by route group and status class\n",
+        );
+        out.push_str("# TYPE http_requests_total counter\n");
+        for group in RouteGroup::ALL {
+            for (class, count) in self.requests[group as usize].iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "http_requests_total{{group=\"{}\",status=\"{}xx\"}} {}",
+                    group.label(),
+                    class + 1,
+                    count.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        out.push_str("# HELP http_request_duration_seconds Time to respond, by route group\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for group in RouteGroup::ALL {
+            self.latency[group as usize].write(
+                &mut out,
+                "http_request_duration_seconds",
+                &format!("group=\"{}\"", group.label()),
+            );
+        }
+
+        for (name, kind, help, value) in [
+            (
+                "forge_cache_hits_total",
+                "counter",
+                "Forge lookups answered from the cache",
+                &self.forge_cache_hits,
+            ),
+            (
+                "forge_cache_misses_total",
+                "counter",
+                "Forge lookups that read the file from disk",
+                &self.forge_cache_misses,
+            ),
+            (
+                "forge_cache_bytes",
+                "gauge",
+                "Bytes held in the forge caches",
+                &self.forge_cache_bytes,
+            ),
+            (
+                "forge_reloads_total",
+                "counter",
+                "Times a forge tree was reloaded",
+                &self.forge_reloads,
+            ),
+            (
+                "forge_reload_failures_total",
+                "counter",
+                "Forge reloads that failed and kept the old tree",
+                &self.forge_reload_failures,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        out.push_str("# HELP forge_reload_duration_seconds Time to reload a forge tree\n");
+        out.push_str("# TYPE forge_reload_duration_seconds histogram\n");
+        self.forge_reload_duration
+            .write(&mut out, "forge_reload_duration_seconds", "");
+
+        if let Some(pool) = pool {
+            for (name, help, value) in [
+                (
+                    "db_pool_connections",
+                    "Open database connections",
+                    pool.size,
+                ),
+                (
+                    "db_pool_idle_connections",
+                    "Open connections not in use",
+                    pool.idle,
+                ),
+                (
+                    "db_pool_max_connections",
+                    "The most connections the pool will open",
+                    pool.max,
+                ),
+            ] {
+                let _ = writeln!(out, "# HELP {name} {help}");
+                let _ = writeln!(out, "# TYPE {name} gauge");
+                let _ = writeln!(out, "{name} {value}");
+            }
+        }
+        out
+    }
+}
+
+/// Middleware counting every request and how long it took
+pub async fn track(request: Request, next: Next) -> Response {
+    let Some(group) = RouteGroup::from_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let start = Instant::now();
+    let response = next.run(request).await;
+    METRICS.record_request(group, response.status().as_u16(), start.elapsed());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups() {
+        assert_eq!(
+            RouteGroup::from_path("/cdn/site/pkg/a.js"),
+            Some(RouteGroup::Cdn)
+        );
+        assert_eq!(
+            RouteGroup::from_path("/api/get_posts"),
+            Some(RouteGroup::Api)
+        );
+        assert_eq!(RouteGroup::from_path("/blog/hello"), Some(RouteGroup::Ssr));
+        assert_eq!(RouteGroup::from_path("/"), Some(RouteGroup::Ssr));
+        assert_eq!(RouteGroup::from_path("/metrics"), None);
+    }
+
+    #[test]
+    fn renders() {
+        let metrics = Metrics::new();
+        metrics.record_request(RouteGroup::Api, 200, Duration::from_millis(20));
+        metrics.record_request(RouteGroup::Api, 503, Duration::from_secs(30));
+        let out = metrics.render(Some(PoolStats {
+            size: 2,
+            idle: 1,
+            max: 8,
+        }));
+        for line in [
+            "http_requests_total{group=\"api\",status=\"2xx\"} 1",
+            "http_requests_total{group=\"api\",status=\"5xx\"} 1",
+            "http_requests_total{group=\"cdn\",status=\"2xx\"} 0",
+            "http_request_duration_seconds_bucket{group=\"api\",le=\"0.01\"} 0",
+            "http_request_duration_seconds_bucket{group=\"api\",le=\"0.025\"} 1",
+            "http_request_duration_seconds_bucket{group=\"api\",le=\"10\"} 1",
+            "http_request_duration_seconds_bucket{group=\"api\",le=\"+Inf\"} 2",
+            "http_request_duration_seconds_sum{group=\"api\"} 30.02",
+            "forge_reload_duration_seconds_count 0",
+            "db_pool_idle_connections 1",
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {line} in\n{out}");
+        }
+    }
+}
@@ -0,0 +1,432 @@
+// Jackson Coxson
+//
+// Per-IP limits, tracked in memory so a restart forgives everyone.
+//
+// The forms people can submit use `RateLimiter`: `limit` submissions per sliding `window`.
+// Everything else goes through the `limit` middleware, which gives each IP a token bucket
+// of requests per route group, plus a budget of bytes for /cdn downloads.
+// Clients over their limit get a 429 with Retry-After.
+//
+// RATE_LIMIT_API, RATE_LIMIT_CDN and RATE_LIMIT_SSR are "burst,per_second", or "off".
+// RATE_LIMIT_CDN_BYTES is the same in bytes, with K, M or G suffixes allowed.
+// TRUSTED_PROXIES lists the addresses (or CIDR ranges) allowed to set X-Forwarded-For,
+// it defaults to loopback for a reverse proxy on the same machine.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use crate::metrics::RouteGroup;
+
+/// Forget about quiet IPs once this many are being tracked
+const PRUNE_AT: usize = 10_000;
+/// Where submissions we can't place are counted, together
+const UNKNOWN_CLIENT: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<HashMap<IpAddr, VecDeque<Instant>>>>,
+    limit: usize,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            inner: Arc::default(),
+            limit,
+            window,
+        }
+    }
+
+    /// Counts a submission from `ip`, or returns how long until it would be allowed.
+    /// Without an ip it counts against one limit shared by everyone else without one.
+    pub fn check(&self, ip: Option<IpAddr>) -> Result<(), Duration> {
+        self.check_at(ip.unwrap_or(UNKNOWN_CLIENT), Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.len() >= PRUNE_AT {
+            let window = self.window;
+            inner.retain(|_, hits| hits.back().is_some_and(|h| now - *h < window));
+        }
+
+        let hits = inner.entry(ip).or_default();
+        while hits.front().is_some_and(|h| now - *h >= self.window) {
+            hits.pop_front();
+        }
+        if hits.len() >= self.limit {
+            let oldest = hits.front().copied().unwrap_or(now);
+            return Err(self.window - (now - oldest));
+        }
+        hits.push_back(now);
+        Ok(())
+    }
+}
+
+/// A token bucket per IP. Each IP starts with `capacity` tokens and gets `per_second` back,
+/// up to `capacity` again.
+#[derive(Clone)]
+pub struct TokenBuckets {
+    inner: Arc<Mutex<HashMap<IpAddr, (f64, Instant)>>>,
+    capacity: f64,
+    per_second: f64,
+}
+
+impl TokenBuckets {
+    pub fn new(capacity: f64, per_second: f64) -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity,
+            per_second,
+        }
+    }
+
+    /// Takes `cost` tokens from `ip`, or returns how long until it has that many.
+    /// A cost of 0 only checks that the bucket isn't in debt.
+    pub fn take(&self, ip: IpAddr, cost: f64) -> Result<(), Duration> {
+        self.take_at(ip, cost, Instant::now())
+    }
+
+    /// Takes `cost` tokens even if `ip` doesn't have them, for things only known afterwards
+    pub fn charge(&self, ip: IpAddr, cost: f64) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let tokens = self.refill(inner.get(&ip), now);
+        inner.insert(ip, (tokens - cost, now));
+    }
+
+    fn take_at(&self, ip: IpAddr, cost: f64, now: Instant) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.len() >= PRUNE_AT {
+            inner.retain(|_, bucket| self.refill(Some(bucket), now) < self.capacity);
+        }
+
+        let tokens = self.refill(inner.get(&ip), now);
+        if tokens < cost {
+            inner.insert(ip, (tokens, now));
+            return Err(Duration::from_secs_f64((cost - tokens) / self.per_second));
+        }
+        inner.insert(ip, (tokens - cost, now));
+        Ok(())
+    }
+
+    /// How many tokens a bucket has by `now`, a new one is full
+    fn refill(&self, bucket: Option<&(f64, Instant)>, now: Instant) -> f64 {
+        match bucket {
+            Some((tokens, at)) => {
+                let elapsed = now.saturating_duration_since(*at).as_secs_f64();
+                (tokens + elapsed * self.per_second).min(self.capacity)
+            }
+            None => self.capacity,
+        }
+    }
+}
+
+/// The limits for every route group, see the top of this file
+#[derive(Clone)]
+pub struct RateLimits {
+    api: Option<TokenBuckets>,
+    cdn: Option<TokenBuckets>,
+    ssr: Option<TokenBuckets>,
+    cdn_bytes: Option<TokenBuckets>,
+    trusted_proxies: Vec<(IpAddr, u8)>,
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        let buckets = |name: &str, default: &str| {
+            let value = std::env::var(name).unwrap_or_else(|_| default.to_string());
+            parse_limit(&value).unwrap_or_else(|| {
+                tracing::warn!("{name} should look like \"burst,per_second\", using {default}");
+                parse_limit(default).flatten()
+            })
+        };
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_else(|_| "127.0.0.1,::1".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .filter_map(|p| {
+                let range = parse_range(p);
+                if range.is_none() {
+                    tracing::warn!("Ignoring {p:?} in TRUSTED_PROXIES");
+                }
+                range
+            })
+            .collect();
+        Self {
+            api: buckets("RATE_LIMIT_API", "60,2"),
+            cdn: buckets("RATE_LIMIT_CDN", "300,30"),
+            ssr: buckets("RATE_LIMIT_SSR", "120,4"),
+            // The wasm bundle alone is a few megabytes
+            cdn_bytes: buckets("RATE_LIMIT_CDN_BYTES", "256M,4M"),
+            trusted_proxies,
+        }
+    }
+
+    fn requests(&self, group: RouteGroup) -> Option<&TokenBuckets> {
+        match group {
+            RouteGroup::Api => self.api.as_ref(),
+            RouteGroup::Cdn => self.cdn.as_ref(),
+            RouteGroup::Ssr => self.ssr.as_ref(),
+        }
+    }
+
+    /// Walks back through X-Forwarded-For while the hops are proxies we trust.
+    /// Anything before the first hop we don't trust is up to the client.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .collect::<Vec<_>>();
+        for hop in forwarded.iter().rev() {
+            if !self.is_trusted(client) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|(range, prefix)| in_range(ip, *range, *prefix))
+    }
+}
+
+/// "burst,per_second", None when it can't be read and Some(None) when it's "off"
+fn parse_limit(value: &str) -> Option<Option<TokenBuckets>> {
+    if value.trim() == "off" {
+        return Some(None);
+    }
+    let (burst, per_second) = value.split_once(',')?;
+    let (burst, per_second) = (parse_amount(burst)?, parse_amount(per_second)?);
+    (burst > 0.0 && per_second > 0.0).then(|| Some(TokenBuckets::new(burst, per_second)))
+}
+
+fn parse_amount(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last()? {
+        (i, 'K' | 'k') => (&value[..i], 1024.0),
+        (i, 'M' | 'm') => (&value[..i], 1024.0 * 1024.0),
+        (i, 'G' | 'g') => (&value[..i], 1024.0 * 1024.0 * 1024.0),
+        _ => (value, 1.0),
+    };
+    Some(number.trim().parse::<f64>().ok()? * multiplier)
+}
+
+/// An address, or a range like 10.0.0.0/8
+fn parse_range(value: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match value.split_once('/') {
+        Some((ip, prefix)) => (ip.parse().ok()?, prefix.parse().ok()?),
+        None => {
+            let ip: IpAddr = value.parse().ok()?;
+            (ip, if ip.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let max = if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 };
+    (prefix <= max).then_some((ip, prefix))
+}
+
+fn in_range(ip: IpAddr, range: IpAddr, prefix: u8) -> bool {
+    let (ip, range, bits) = match (ip.to_canonical(), range) {
+        (IpAddr::V4(ip), IpAddr::V4(range)) => {
+            (u32::from(ip) as u128, u32::from(range) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(range)) => (u128::from(ip), u128::from(range), 128),
+        _ => return false,
+    };
+    let shift = bits - prefix as u32;
+    shift == bits || ip >> shift == range >> shift
+}
+
+/// Who a request is from, after looking through trusted proxies
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+/// Middleware applying RateLimits, and recording the ClientIp for everything after it
+pub async fn limit(State(limits): State<RateLimits>, mut request: Request, next: Next) -> Response {
+    let Some(ConnectInfo(peer)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    else {
+        return next.run(request).await;
+    };
+    let ip = limits.client_ip(peer.ip(), request.headers());
+    request.extensions_mut().insert(ClientIp(ip));
+
+    let Some(group) = RouteGroup::from_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let bytes = match group {
+        RouteGroup::Cdn => limits.cdn_bytes.as_ref(),
+        _ => None,
+    };
+    let allowed = limits
+        .requests(group)
+        .map_or(Ok(()), |requests| requests.take(ip, 1.0))
+        .and_then(|_| bytes.map_or(Ok(()), |bytes| bytes.take(ip, 0.0)));
+    if let Err(wait) = allowed {
+        tracing::info!("Rate limited {ip} on {}", request.uri().path());
+        return too_many_requests(wait);
+    }
+
+    let response = next.run(request).await;
+    if let Some(bytes) = bytes {
+        let sent = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get("content-length")?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()
+        });
+        bytes.charge(ip, sent.unwrap_or_default() as f64);
+    }
+    response
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("Too many requests, try again in {seconds} seconds"),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert("retry-after", HeaderValue::from(seconds));
+    response
+}
+
+/// Where the current request came from, as worked out by the `limit` middleware
+pub async fn client_ip() -> Option<IpAddr> {
+    if let Ok(Extension(ClientIp(ip))) = leptos_axum::extract::<Extension<ClientIp>>().await {
+        return Some(ip);
+    }
+    match leptos_axum::extract::<ConnectInfo<SocketAddr>>().await {
+        Ok(ConnectInfo(addr)) => Some(addr.ip()),
+        Err(_) => {
+            tracing::warn!(
+                "No client address for this request, is the server missing ConnectInfo?"
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sliding_window() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(ip, start).is_ok());
+        assert!(limiter
+            .check_at(ip, start + Duration::from_secs(10))
+            .is_ok());
+        assert_eq!(
+            limiter.check_at(ip, start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(limiter.check_at(other, start).is_ok());
+        assert!(limiter
+            .check_at(ip, start + Duration::from_secs(60))
+            .is_ok());
+
+        // Unknown clients aren't let through, they share a limit
+        assert!(limiter.check(None).is_ok());
+        assert!(limiter.check(None).is_ok());
+        assert!(limiter.check(None).is_err());
+    }
+
+    #[test]
+    fn token_buckets() {
+        let buckets = TokenBuckets::new(2.0, 1.0);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(buckets.take_at(ip, 1.0, start).is_ok());
+        assert!(buckets.take_at(ip, 1.0, start).is_ok());
+        assert_eq!(buckets.take_at(ip, 1.0, start), Err(Duration::from_secs(1)));
+        assert!(buckets
+            .take_at(ip, 1.0, start + Duration::from_secs(1))
+            .is_ok());
+
+        // Downloads are charged afterwards and can leave the bucket in debt
+        let bytes = TokenBuckets::new(100.0, 10.0);
+        bytes.charge(ip, 300.0);
+        let wait = bytes.take(ip, 0.0).unwrap_err();
+        assert!(wait > Duration::from_secs(19) && wait <= Duration::from_secs(20));
+    }
+
+    #[test]
+    fn limits() {
+        assert!(parse_limit("off").unwrap().is_none());
+        assert!(parse_limit("60").is_none());
+        assert!(parse_limit("0,1").is_none());
+        let bytes = parse_limit("256M, 4M").unwrap().unwrap();
+        assert_eq!(bytes.capacity, 256.0 * 1024.0 * 1024.0);
+        assert_eq!(bytes.per_second, 4.0 * 1024.0 * 1024.0);
+    }
+
+    #[test]
+    fn forwarded() {
+        let limits = RateLimits {
+            api: None,
+            cdn: None,
+            ssr: None,
+            cdn_bytes: None,
+            trusted_proxies: ["127.0.0.1", "::1", "10.0.0.0/8"]
+                .into_iter()
+                .filter_map(parse_range)
+                .collect(),
+        };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(limits.client_ip(ip("127.0.0.1"), &headers), ip("127.0.0.1"));
+
+        headers.insert(
+            "x-forwarded-for",
+            "1.2.3.4, 5.6.7.8, 10.1.2.3".parse().unwrap(),
+        );
+        // Through our proxy and a trusted load balancer
+        assert_eq!(limits.client_ip(ip("127.0.0.1"), &headers), ip("5.6.7.8"));
+        // Straight from the internet, the header is made up
+        assert_eq!(limits.client_ip(ip("9.9.9.9"), &headers), ip("9.9.9.9"));
+        assert_eq!(limits.client_ip(ip("::1"), &headers), ip("5.6.7.8"));
+
+        assert!(in_range(ip("::ffff:10.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(!in_range(ip("11.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(in_range(ip("11.0.0.1"), ip("0.0.0.0"), 0));
+        assert_eq!(parse_range("10.0.0.0/33"), None);
+    }
+}
@@ -0,0 +1,248 @@
+// Jackson Coxson
+//
+// Security headers for every response.
+// Pages get a Content-Security-Policy with a fresh nonce, which leptos puts on the
+// hydration scripts and we put on the few scripts the shell loads. Everything else
+// gets the same policy with the nonce left out.
+//
+// Each header can be replaced by setting an environment variable of the same name,
+// or turned off by setting it to "off":
+// CONTENT_SECURITY_POLICY ({nonce} is filled in), STRICT_TRANSPORT_SECURITY,
+// REFERRER_POLICY, PERMISSIONS_POLICY and USB_PERMISSIONS_POLICY (for /idevice-tools).
+// CSP_REPORT_ONLY=true only reports violations, to CSP_REPORT_URI if it's set.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval' https://analytics.jkcoxson.com https://cdn.jsdelivr.net; \
+    style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: https:; \
+    media-src 'self' https:; \
+    connect-src 'self' https://analytics.jkcoxson.com https://jitstreamer-api.jkcoxson.com; \
+    frame-src https://www.youtube-nocookie.com; \
+    object-src 'none'; \
+    base-uri 'self'; \
+    form-action 'self'; \
+    frame-ancestors 'none'";
+
+const STRICT_TRANSPORT_SECURITY: &str = "max-age=63072000; includeSubDomains";
+const REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+const PERMISSIONS_POLICY: &str = "camera=(), microphone=(), geolocation=(), payment=(), usb=()";
+/// The idevice tools talk to phones over WebUSB
+const USB_PERMISSIONS_POLICY: &str =
+    "camera=(), microphone=(), geolocation=(), payment=(), usb=(self)";
+
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    content_security_policy: Option<String>,
+    report_only: bool,
+    strict_transport_security: Option<HeaderValue>,
+    referrer_policy: Option<HeaderValue>,
+    permissions_policy: Option<HeaderValue>,
+    usb_permissions_policy: Option<HeaderValue>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            content_security_policy: Some(CONTENT_SECURITY_POLICY.to_string()),
+            report_only: false,
+            strict_transport_security: Some(HeaderValue::from_static(STRICT_TRANSPORT_SECURITY)),
+            referrer_policy: Some(HeaderValue::from_static(REFERRER_POLICY)),
+            permissions_policy: Some(HeaderValue::from_static(PERMISSIONS_POLICY)),
+            usb_permissions_policy: Some(HeaderValue::from_static(USB_PERMISSIONS_POLICY)),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let header = |name: &str, default: Option<HeaderValue>| match std::env::var(name) {
+            Ok(v) if v == "off" => None,
+            Ok(v) => match HeaderValue::from_str(&v) {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    tracing::warn!("{name} isn't a valid header value, using the default");
+                    default
+                }
+            },
+            Err(_) => default,
+        };
+
+        let mut content_security_policy = match std::env::var("CONTENT_SECURITY_POLICY") {
+            Ok(v) if v == "off" => None,
+            Ok(v) => Some(v),
+            Err(_) => defaults.content_security_policy,
+        };
+        if let (Some(policy), Ok(uri)) = (
+            &mut content_security_policy,
+            std::env::var("CSP_REPORT_URI"),
+        ) {
+            policy.push_str(&format!("; report-uri {uri}"));
+        }
+        Self {
+            content_security_policy,
+            report_only: std::env::var("CSP_REPORT_ONLY").is_ok_and(|v| v == "true" || v == "1"),
+            strict_transport_security: header(
+                "STRICT_TRANSPORT_SECURITY",
+                defaults.strict_transport_security,
+            ),
+            referrer_policy: header("REFERRER_POLICY", defaults.referrer_policy),
+            permissions_policy: header("PERMISSIONS_POLICY", defaults.permissions_policy),
+            usb_permissions_policy: header(
+                "USB_PERMISSIONS_POLICY",
+                defaults.usb_permissions_policy,
+            ),
+        }
+    }
+
+    fn csp_header(&self) -> HeaderName {
+        if self.report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        }
+    }
+
+    /// The policy with `nonce` filled in, or with the nonce sources dropped without one
+    fn policy(&self, nonce: Option<&str>) -> Option<HeaderValue> {
+        let policy = self.content_security_policy.as_ref()?;
+        let policy = match nonce {
+            Some(nonce) => policy.replace("{nonce}", nonce),
+            None => policy
+                .split(';')
+                .map(|directive| {
+                    directive
+                        .split_whitespace()
+                        .filter(|source| !source.contains("{nonce}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+        };
+        match HeaderValue::from_str(&policy) {
+            Ok(v) => Some(v),
+            Err(_) => {
+                tracing::warn!("CONTENT_SECURITY_POLICY isn't a valid header value");
+                None
+            }
+        }
+    }
+
+    /// Everything but the page's policy, which `provide_page_policy` sets
+    fn apply(&self, path: &str, headers: &mut HeaderMap) {
+        let permissions = if path.starts_with("/idevice-tools") {
+            &self.usb_permissions_policy
+        } else {
+            &self.permissions_policy
+        };
+        for (name, value) in [
+            (
+                header::STRICT_TRANSPORT_SECURITY,
+                self.strict_transport_security.clone(),
+            ),
+            (
+                header::X_CONTENT_TYPE_OPTIONS,
+                Some(HeaderValue::from_static("nosniff")),
+            ),
+            (header::REFERRER_POLICY, self.referrer_policy.clone()),
+            (
+                HeaderName::from_static("permissions-policy"),
+                permissions.clone(),
+            ),
+            (self.csp_header(), self.policy(None)),
+        ] {
+            if let Some(value) = value {
+                headers.entry(name).or_insert(value);
+            }
+        }
+    }
+}
+
+/// Middleware adding the security headers to anything that doesn't have them yet
+pub async fn headers(
+    State(security): State<SecurityHeaders>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    security.apply(&path, response.headers_mut());
+    response
+}
+
+/// Called from the shell. Makes a nonce for this page's scripts and sends the policy allowing them.
+pub fn provide_page_policy() {
+    leptos::nonce::provide_nonce();
+    let (Some(context), Some(nonce), Some(response)) = (
+        leptos::prelude::use_context::<crate::context::Context>(),
+        leptos::nonce::use_nonce(),
+        leptos::prelude::use_context::<leptos_axum::ResponseOptions>(),
+    ) else {
+        return;
+    };
+    if let Some(policy) = context.security.policy(Some(&nonce.to_string())) {
+        response.insert_header(context.security.csp_header(), policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies() {
+        let security = SecurityHeaders::default();
+        let page = security.policy(Some("abc")).unwrap();
+        assert!(page
+            .to_str()
+            .unwrap()
+            .contains("script-src 'self' 'nonce-abc' 'wasm-unsafe-eval'"));
+
+        let other = security.policy(None).unwrap();
+        let other = other.to_str().unwrap();
+        assert!(!other.contains("nonce"));
+        assert!(other.contains("; script-src 'self' 'wasm-unsafe-eval' https://"));
+        assert!(other.ends_with("frame-ancestors 'none'"));
+    }
+
+    #[test]
+    fn headers() {
+        let security = SecurityHeaders {
+            report_only: true,
+            referrer_policy: None,
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY,
+            HeaderValue::from_static("from the page"),
+        );
+        security.apply("/idevice-tools/afc", &mut headers);
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY_REPORT_ONLY],
+            "from the page"
+        );
+        assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(!headers.contains_key(header::REFERRER_POLICY));
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(headers["permissions-policy"]
+            .to_str()
+            .unwrap()
+            .contains("usb=(self)"));
+
+        let mut headers = HeaderMap::new();
+        security.apply("/blog", &mut headers);
+        assert!(headers["permissions-policy"]
+            .to_str()
+            .unwrap()
+            .contains("usb=()"));
+    }
+}
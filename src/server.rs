@@ -0,0 +1,94 @@
+// Jackson Coxson
+//
+// Getting the socket and letting go of it.
+// The listener is inherited when there is one, from systemd socket activation
+// (see deploy/) or anything else speaking LISTEN_FDS, so a restart never closes it.
+// The first inherited socket is for HTTP and the second for HTTPS, see tls.rs.
+// SIGTERM or ctrl-c stops accepting connections and lets the open ones finish,
+// for up to SHUTDOWN_TIMEOUT seconds (30 by default).
+
+use std::{future::IntoFuture, net::SocketAddr, time::Duration};
+
+use axum::Router;
+use tokio::net::TcpListener;
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The inherited socket at `index` if there is one, otherwise a new one bound to `addr`
+pub async fn listener(index: usize, addr: SocketAddr) -> std::io::Result<TcpListener> {
+    if let Some(listener) = listenfd::ListenFd::from_env().take_tcp_listener(index)? {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        tracing::info!("Using the inherited socket on {}", listener.local_addr()?);
+        return Ok(listener);
+    }
+    TcpListener::bind(addr).await
+}
+
+/// Resolves on SIGTERM, or ctrl-c
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Unable to listen for ctrl-c: {e:?}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Unable to listen for SIGTERM: {e:?}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+pub(crate) fn shutdown_timeout() -> Duration {
+    std::env::var("SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(SHUTDOWN_TIMEOUT)
+}
+
+/// Serves `app` until a shutdown signal, then drains the open connections.
+/// Connection info is how requests are rate limited.
+pub async fn serve(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    let (signalled, on_signal) = tokio::sync::oneshot::channel();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down, waiting for open connections");
+        let _ = signalled.send(());
+    });
+
+    let timeout = shutdown_timeout();
+    tokio::select! {
+        result = server.into_future() => result,
+        _ = async {
+            if on_signal.await.is_ok() {
+                tokio::time::sleep(timeout).await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        } => {
+            tracing::warn!("Connections still open after {timeout:?}, closing them");
+            Ok(())
+        }
+    }
+}
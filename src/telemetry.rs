@@ -0,0 +1,39 @@
+// Jackson Coxson
+//
+// Server logs go through tracing.
+// RUST_LOG picks the levels (info by default), LOG_FORMAT=json writes one JSON object
+// per line for log collectors and LOG_FORMAT=pretty spreads each event over a few lines.
+// Every request gets an x-request-id, which is on its span and echoed in the response.
+
+use axum::{body::Body, http::Request};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// Used when RUST_LOG isn't set, sqlx logs every statement at info
+const DEFAULT_FILTER: &str = "info,sqlx=warn";
+
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => builder.json().init(),
+        Ok("pretty") => builder.pretty().init(),
+        _ => builder.init(),
+    }
+}
+
+/// The span each request is handled in, for tower_http's TraceLayer
+pub fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
+}
@@ -0,0 +1,296 @@
+// Jackson Coxson
+//
+// Serving HTTPS ourselves, for small deployments without a reverse proxy in front.
+// With TLS_CERT and TLS_KEY pointing at PEM files, the site is served over TLS on
+// TLS_ADDR (0.0.0.0:443 by default), over HTTP/2 for clients that offer it, and the
+// usual address only redirects to HTTPS. The files are watched, so a renewed
+// certificate is picked up without a restart.
+// WebUSB only works in a secure context, so the idevice tools need this or a proxy.
+
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::Request,
+    http::{header, uri::PathAndQuery},
+    response::Redirect,
+    Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+use tower::Service;
+
+use crate::server;
+
+const DEFAULT_ADDR: &str = "0.0.0.0:443";
+/// Clients get this long to finish the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct TlsConfig {
+    pub addr: SocketAddr,
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl TlsConfig {
+    /// None unless both TLS_CERT and TLS_KEY are set
+    pub fn from_env() -> Option<Self> {
+        let (Ok(cert), Ok(key)) = (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) else {
+            return None;
+        };
+        let addr = match std::env::var("TLS_ADDR").map(|a| a.parse()) {
+            Ok(Ok(addr)) => addr,
+            Ok(Err(_)) => {
+                tracing::warn!("TLS_ADDR isn't a socket address, using {DEFAULT_ADDR}");
+                DEFAULT_ADDR.parse().unwrap()
+            }
+            Err(_) => DEFAULT_ADDR.parse().unwrap(),
+        };
+        Some(Self {
+            addr,
+            // Not canonicalized, certbot renews by pointing the same path somewhere new
+            cert: std::path::absolute(cert).ok()?,
+            key: std::path::absolute(key).ok()?,
+        })
+    }
+}
+
+/// The config new connections are accepted with, replaced when the files change
+struct Certificates {
+    cert: PathBuf,
+    key: PathBuf,
+    config: RwLock<Arc<ServerConfig>>,
+}
+
+impl Certificates {
+    fn new(cert: PathBuf, key: PathBuf) -> io::Result<Self> {
+        let config = Self::load(&cert, &key)?;
+        Ok(Self {
+            cert,
+            key,
+            config: RwLock::new(Arc::new(config)),
+        })
+    }
+
+    fn load(cert: &Path, key: &Path) -> io::Result<ServerConfig> {
+        let chain = CertificateDer::pem_file_iter(cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| io::Error::other(format!("{}: {e}", cert.display())))?;
+        let key = PrivateKeyDer::from_pem_file(key)
+            .map_err(|e| io::Error::other(format!("{}: {e}", key.display())))?;
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .map_err(io::Error::other)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    fn current(&self) -> Arc<ServerConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Keeps the old certificate if the new files don't load, they're often
+    /// written one at a time and the next change will try again
+    fn reload(&self) {
+        match Self::load(&self.cert, &self.key) {
+            Ok(config) => {
+                *self.config.write().unwrap() = Arc::new(config);
+                tracing::info!("Reloaded the TLS certificate");
+            }
+            Err(e) => {
+                tracing::warn!("Keeping the old TLS certificate, the new one didn't load: {e}")
+            }
+        }
+    }
+
+    /// Watches the folders holding the files, which sees them being replaced too.
+    /// Changes stop being picked up once the watcher is dropped.
+    fn watch(self: &Arc<Self>) -> notify::Result<notify::RecommendedWatcher> {
+        let certificates = self.clone();
+        let mut watcher = notify::recommended_watcher(
+            move |res: Result<notify::Event, notify::Error>| match res {
+                Ok(event) => {
+                    if let notify::EventKind::Create(_) | notify::EventKind::Modify(_) = event.kind
+                    {
+                        if event
+                            .paths
+                            .iter()
+                            .any(|p| *p == certificates.cert || *p == certificates.key)
+                        {
+                            certificates.reload();
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("TLS watch error: {e:?}"),
+            },
+        )?;
+        for file in [&self.cert, &self.key] {
+            if let Some(folder) = file.parent() {
+                notify::Watcher::watch(&mut watcher, folder, notify::RecursiveMode::NonRecursive)?;
+            }
+        }
+        Ok(watcher)
+    }
+}
+
+/// Where a request to `host` for `path` lives on HTTPS, at `port`
+fn https_url(host: &str, port: u16, path: &str) -> String {
+    // Bracketed IPv6 addresses have colons of their own
+    let name = match host.find(']') {
+        Some(end) => &host[..=end],
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    if port == 443 {
+        format!("https://{name}{path}")
+    } else {
+        format!("https://{name}:{port}{path}")
+    }
+}
+
+/// Everything on plain HTTP gets sent to HTTPS, except health checks
+fn redirect(port: u16) -> Router {
+    Router::new()
+        .route("/healthz", axum::routing::get(crate::health::healthz))
+        .fallback(move |request: Request| async move {
+            let host = request
+                .headers()
+                .get(header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .or(request.uri().host())
+                .unwrap_or("localhost");
+            let path = request
+                .uri()
+                .path_and_query()
+                .map(PathAndQuery::as_str)
+                .unwrap_or("/");
+            Redirect::permanent(&https_url(host, port, path))
+        })
+}
+
+/// Serves `app` over TLS on `https`, and redirects `http` to it, until a shutdown signal.
+/// Open connections are drained the same way server::serve drains them.
+pub async fn serve(
+    http: TcpListener,
+    https: TcpListener,
+    tls: TlsConfig,
+    app: Router,
+) -> io::Result<()> {
+    let certificates = Arc::new(Certificates::new(tls.cert, tls.key)?);
+    let _watcher = match certificates.watch() {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            tracing::warn!("Unable to watch the TLS files, renewals need a restart: {e:?}");
+            None
+        }
+    };
+    let port = https.local_addr()?.port();
+    tokio::try_join!(
+        server::serve(http, redirect(port)),
+        serve_https(https, certificates, app)
+    )?;
+    Ok(())
+}
+
+async fn serve_https(
+    listener: TcpListener,
+    certificates: Arc<Certificates>,
+    app: Router,
+) -> io::Result<()> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    // Connection info is how requests are rate limited
+    let mut make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    let signal = server::shutdown_signal();
+    tokio::pin!(signal);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors, give some connections time to close
+                    tracing::warn!("Unable to accept a connection: {e}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let acceptor = TlsAcceptor::from(certificates.current());
+        let service = make_service.call(peer).await.unwrap_or_else(|e| match e {});
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!("TLS handshake with {peer} failed: {e}");
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {peer} timed out");
+                        return;
+                    }
+                };
+            let connection = builder
+                .serve_connection_with_upgrades(
+                    TokioIo::new(stream),
+                    TowerToHyperService::new(service),
+                )
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                tracing::debug!("Connection from {peer} closed: {e}");
+            }
+        });
+    }
+
+    drop(listener);
+    tracing::info!("Shutting down, waiting for open TLS connections");
+    let timeout = server::shutdown_timeout();
+    if tokio::time::timeout(timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        tracing::warn!("TLS connections still open after {timeout:?}, closing them");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects() {
+        assert_eq!(
+            https_url("jkcoxson.com", 443, "/blog?page=2"),
+            "https://jkcoxson.com/blog?page=2"
+        );
+        assert_eq!(
+            https_url("localhost:3002", 3443, "/"),
+            "https://localhost:3443/"
+        );
+        assert_eq!(
+            https_url("[::1]:3002", 443, "/idevice-tools"),
+            "https://[::1]/idevice-tools"
+        );
+    }
+}